@@ -1,9 +1,10 @@
 use aurora_fsm::state::State;
 use aurora_fsm::state_machine::StateMachine;
 use event_gen::event_generator::EventGenerator;
+use event_gen::event_queue::EventQueue;
 use event_gen::generators::one_shot_generator::OneShotGenerator;
 use std::borrow::{Borrow, BorrowMut};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::sync::mpsc::Receiver;
 
@@ -45,7 +46,7 @@ impl State<Event> for AState {
         let one_shot = OneShotGenerator {
             value: Event::OneShot,
         };
-        one_shot.start(sender);
+        one_shot.start(sender).unwrap();
 
         return receiver;
     }
@@ -77,7 +78,7 @@ impl State<Event> for BState {
         let one_shot = OneShotGenerator {
             value: Event::OneShot,
         };
-        one_shot.start(sender);
+        one_shot.start(sender).unwrap();
 
         return receiver;
     }
@@ -87,6 +88,49 @@ impl State<Event> for BState {
     }
 }
 
+struct QueueState {
+    handled: Rc<Cell<u32>>,
+    queue: Option<EventQueue<Event>>,
+}
+
+impl State<Event> for QueueState {
+    fn handle_event(&mut self, _event: Event) -> Option<Box<dyn State<Event>>> {
+        self.handled.set(self.handled.get() + 1);
+        None
+    }
+
+    fn create_event_sources(&mut self) -> Receiver<Event> {
+        let (mut queue, receiver) = EventQueue::new();
+        for _ in 0..2 {
+            OneShotGenerator {
+                value: Event::OneShot,
+            }
+            .start_into(&mut queue)
+            .unwrap();
+        }
+        self.queue = Some(queue);
+
+        receiver
+    }
+
+    fn destroy_event_sources(&mut self) {
+        self.queue.take();
+    }
+}
+
+#[test]
+fn run_fsm_with_event_queue() {
+    let handled = Rc::new(Cell::new(0));
+    let mut fsm = StateMachine::new(QueueState {
+        handled: handled.clone(),
+        queue: None,
+    });
+
+    fsm.step();
+    fsm.step();
+    assert_eq!(handled.get(), 2);
+}
+
 #[test]
 fn run_basic_fsm() {
     let state_ident = Rc::new(RefCell::new(StateIdent::AInit));
@@ -0,0 +1,23 @@
+//! Code snippets shared by the rules of `expression_parser.lalrpop`.
+
+/// A variable or function value as an operand of an expression, f64 for numbers.
+pub(crate) fn operand(value: &str) -> String {
+    format!("::aurora_hal::expr::Operand::operand({value})")
+}
+
+/// `var op= value`, e.g. `process.Sensor1.pressure += 2`.
+pub(crate) fn compound(var: &str, op: &str, value: &str) -> String {
+    let current = operand(&format!("::aurora_hal::GetterSetter::get(&{var})"));
+    format!("::aurora_hal::GetterSetter::set(&{var}, ::aurora_hal::expr::Cast::cast({current} {op} ({value})));")
+}
+
+/// A function over the values of a ring buffer, e.g. `mean(process.Sensor2.x_acc)`.
+pub(crate) fn aggregate(function: &str, var: &str, arg: &str) -> String {
+    format!("::aurora_hal::expr::{function}(&::aurora_hal::ArrayGetter::get_array(&{var}), {arg})")
+}
+
+/// A function without a value for an empty window, like `mean`. The whole condition or callback
+/// has no value then, see `aurora_hal::expr::holds` and `aurora_hal::expr::run`.
+pub(crate) fn window_aggregate(function: &str, var: &str, arg: &str) -> String {
+    format!("{}?", aggregate(function, var, arg))
+}
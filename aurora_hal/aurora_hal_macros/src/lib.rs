@@ -2,25 +2,53 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
+use std::collections::HashMap;
 use syn::{parse::Parser, parse_macro_input, DeriveInput};
 use toml::Value;
 
 #[macro_use]
 extern crate lalrpop_util;
 
+mod expression;
 lalrpop_mod!(expression_parser);
 
+/// Reads a config file given relative to the manifest directory of the crate invoking the macro.
+///
+/// Returns the absolute path of the file together with its contents. Expansions `include_str!`
+/// the absolute path, so that cargo rebuilds the crate when the file changes.
+fn read_config(path: &syn::LitStr) -> syn::Result<(String, String)> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| syn::Error::new(path.span(), "CARGO_MANIFEST_DIR is not set"))?;
+    let full_path = std::path::Path::new(&manifest_dir).join(path.value());
+    let contents = std::fs::read_to_string(&full_path).map_err(|e| {
+        syn::Error::new(
+            path.span(),
+            format!("Couldn't read {}: {e}", full_path.display()),
+        )
+    })?;
+    Ok((full_path.to_string_lossy().into_owned(), contents))
+}
+
+/// Adds the fields defined in an IoTree.toml to the struct, e.g. `#[add_fields("IoTree.toml")]`.
+/// The path is relative to the `Cargo.toml` of the crate using the macro.
+///
 /// # Panics
 ///
-/// Will panic if IoTree.toml is unparseable
+/// Will panic if the IoTree.toml is unparseable
 #[proc_macro_attribute]
-pub fn add_fields(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn add_fields(args: TokenStream, input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(args as syn::LitStr);
     let mut ast = parse_macro_input!(input as DeriveInput);
     //let name = &ast.ident;
 
     //Import file that defines the required struct
-    let toml = include_str!("IoTree.toml");
-    let toml = toml.parse::<Value>().expect("Couldn't parse IoTree.toml");
+    let (full_path, toml) = match read_config(&path) {
+        Ok(config) => config,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let toml = toml
+        .parse::<Value>()
+        .unwrap_or_else(|e| panic!("Couldn't parse {full_path}: {e}"));
 
     //Add the fields for Control and Process Variables to the Struct
     match &mut ast.data {
@@ -43,8 +71,16 @@ pub fn add_fields(_args: TokenStream, input: TokenStream) -> TokenStream {
         }
     }
 
-    let (_process_rest, process_def_opt) = build_struct("ProcessVar", &toml["Process"]);
-    let (_control_rest, control_def_opt) = build_struct("ControlVar", &toml["Control"]);
+    // Variables are numbered in the order of PathRegistry::paths()
+    let ids: HashMap<String, usize> = tree_leaves(&toml)
+        .into_iter()
+        .enumerate()
+        .map(|(id, (path, _, _))| (path, id))
+        .collect();
+    let (_process_rest, process_def_opt) =
+        build_struct("ProcessVar", &toml["Process"], "process", &ids);
+    let (_control_rest, control_def_opt) =
+        build_struct("ControlVar", &toml["Control"], "control", &ids);
 
     let process_def: proc_macro2::TokenStream = match process_def_opt {
         Some(x) => x,
@@ -60,34 +96,398 @@ pub fn add_fields(_args: TokenStream, input: TokenStream) -> TokenStream {
     .parse()
     .unwrap();
 
+    let registry_def: proc_macro2::TokenStream = build_registry(&ast.ident.to_string(), &toml)
+        .parse()
+        .unwrap();
+    let snapshot_def: proc_macro2::TokenStream = build_snapshot_impl(&ast.ident.to_string(), &toml)
+        .parse()
+        .unwrap();
+    let telemetry_def: proc_macro2::TokenStream = build_telemetry(&ast.ident.to_string(), &toml)
+        .parse()
+        .unwrap();
+
     quote! {
+        const _: &str = include_str!(#full_path);
         #process_def
         #control_def
         #ast
+        #registry_def
+        #snapshot_def
+        #telemetry_def
     }
     .into()
 }
 
-fn build_struct(key: &str, value: &Value) -> (Option<String>, Option<String>) {
-    let mut struct_def = String::new();
-    let mut rest = String::new();
-
+/// Collects the dotted path (e.g. `process.Sensor2.x_acc`), the field access expression (e.g.
+/// `process.m_Sensor2.x_acc`) and the type of every variable below `value`.
+fn collect_leaves(
+    path: &str,
+    access: &str,
+    value: &Value,
+    leaves: &mut Vec<(String, String, String)>,
+) {
     match value {
-        Value::String(var_type) => match var_type.as_str() {
-            "u64" | "u32" | "u16" | "i64" | "i32" | "i16" | "f64" | "f32" | "bool" => {
-                struct_def.push_str(&format!("{key}: Atomic{},\n", var_type.to_uppercase()));
-                (Some(struct_def), None)
+        Value::String(var_type) => {
+            leaves.push((path.to_string(), access.to_string(), var_type.to_string()))
+        }
+        Value::Table(table) => {
+            if let Some(Value::String(var_type)) = table.get("type") {
+                leaves.push((path.to_string(), access.to_string(), var_type.to_string()));
+                return;
+            }
+            for (k, v) in table.iter() {
+                let access = match v {
+                    Value::Table(t) if !t.contains_key("type") => format!("{access}.m_{k}"),
+                    _ => format!("{access}.{k}"),
+                };
+                collect_leaves(&format!("{path}.{k}"), &access, v, leaves);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// All variables of the Process and Control tables, see `collect_leaves`.
+fn tree_leaves(toml: &Value) -> Vec<(String, String, String)> {
+    let mut leaves = Vec::new();
+    if let Some(process) = toml.get("Process") {
+        collect_leaves("process", "process", process, &mut leaves);
+    }
+    if let Some(control) = toml.get("Control") {
+        collect_leaves("control", "control", control, &mut leaves);
+    }
+    leaves
+}
+
+/// Generates `TELEMETRY_CHANNELS` and `TELEMETRY_BYTES_PER_SECOND` for the IoTree struct from the
+/// `[Telemetry]` table:
+///
+/// ```toml
+/// [Telemetry]
+/// link_budget = 1200 # bytes per second, optional
+///     [Telemetry.attitude]
+///     rate = 20 # frames per second
+///     priority = 0 # frames due at the same time are sent by ascending priority
+///     fields = ["process.Imu.x", "process.Imu.y"]
+/// ```
+///
+/// # Panics
+///
+/// Will panic if a channel is invalid, contains a string (which has no fixed size) or if the
+/// channels need more than `link_budget`.
+fn build_telemetry(struct_name: &str, toml: &Value) -> String {
+    let leaves = tree_leaves(toml);
+    let mut channels = String::new();
+    let mut usage = Vec::new();
+    let mut link_budget = None;
+
+    if let Some(Value::Table(telemetry)) = toml.get("Telemetry") {
+        for (name, channel) in telemetry.iter() {
+            let channel = match (name.as_str(), channel) {
+                ("link_budget", Value::Integer(budget)) => {
+                    link_budget = Some(*budget as f64);
+                    continue;
+                }
+                ("link_budget", Value::Float(budget)) => {
+                    link_budget = Some(*budget);
+                    continue;
+                }
+                (_, Value::Table(channel)) => channel,
+                _ => panic!("Unknown entry '{name}' in the Telemetry table"),
+            };
+
+            let rate = match channel.get("rate") {
+                Some(Value::Integer(rate)) if *rate > 0 => *rate as f64,
+                Some(Value::Float(rate)) if *rate > 0.0 => *rate,
+                _ => panic!("Telemetry channel '{name}' needs a positive rate"),
+            };
+            let priority = match channel.get("priority") {
+                Some(Value::Integer(priority)) if (0..=255).contains(priority) => *priority,
+                None => 0,
+                _ => panic!("The priority of telemetry channel '{name}' must be between 0 and 255"),
+            };
+            let fields = match channel.get("fields") {
+                Some(Value::Array(fields)) => fields,
+                _ => panic!("Telemetry channel '{name}' needs a list of fields"),
+            };
+
+            // The header holds the format version and the schema hash, see aurora_hal::telemetry
+            let mut frame_len = 5;
+            let mut field_list = String::new();
+            for field in fields {
+                let path = match field {
+                    Value::String(path) => path,
+                    _ => panic!("The fields of telemetry channel '{name}' must be paths"),
+                };
+                let var_type = leaves
+                    .iter()
+                    .find(|(leaf, _, _)| leaf == path)
+                    .map(|(_, _, var_type)| var_type.as_str())
+                    .unwrap_or_else(|| {
+                        panic!("Unknown variable '{path}' in telemetry channel '{name}'")
+                    });
+                frame_len += match var_type {
+                    "u64" | "i64" | "f64" => 8,
+                    "u32" | "i32" | "f32" => 4,
+                    "u16" | "i16" => 2,
+                    "bool" => 1,
+                    _ => panic!(
+                        "Telemetry channel '{name}' contains '{path}' of type {var_type}, which has no fixed size"
+                    ),
+                };
+                field_list.push_str(&format!("\"{path}\", "));
             }
 
-            "str" => {
-                struct_def.push_str(&format!("{key}: std::sync::RwLock<String>,\n"));
-                (Some(struct_def), None)
+            usage.push((name.clone(), frame_len as f64 * rate));
+            channels.push_str(&format!(
+                "::aurora_hal::telemetry::ChannelInfo {{
+                    name: \"{name}\",
+                    rate: {rate:?},
+                    priority: {priority},
+                    fields: &[{field_list}],
+                    frame_len: {frame_len},
+                }},\n"
+            ));
+        }
+    }
+
+    let bytes_per_second: f64 = usage.iter().map(|(_, bytes)| bytes).sum();
+    if let Some(budget) = link_budget {
+        if bytes_per_second > budget {
+            let channels: Vec<String> = usage
+                .iter()
+                .map(|(name, bytes)| format!("{name}: {bytes} B/s"))
+                .collect();
+            panic!(
+                "Telemetry needs {bytes_per_second} B/s, but the link budget is {budget} B/s ({})",
+                channels.join(", ")
+            );
+        }
+    }
+
+    format!(
+        "impl {struct_name} {{
+            /// The telemetry channels of the `[Telemetry]` table of the IoTree.toml
+            pub const TELEMETRY_CHANNELS: &'static [::aurora_hal::telemetry::ChannelInfo] = &[{channels}];
+            /// The downlink bandwidth all telemetry channels need together
+            pub const TELEMETRY_BYTES_PER_SECOND: f64 = {bytes_per_second:?};
+        }}\n"
+    )
+}
+
+/// Implements `aurora_hal::PathRegistry` for the IoTree struct.
+fn build_registry(struct_name: &str, toml: &Value) -> String {
+    let leaves = tree_leaves(toml);
+
+    let mut paths = String::new();
+    let mut getters = String::new();
+    let mut id_getters = String::new();
+    let mut setters = String::new();
+    // Leaf ids are the indices into paths()
+    for (index, (path, access, var_type)) in leaves.iter().enumerate() {
+        let variant = match var_type.as_str() {
+            "u64" | "u32" | "u16" | "i64" | "i32" | "i16" | "f64" | "f32" => {
+                var_type.to_uppercase()
             }
+            "bool" => "Bool".to_string(),
+            "str" => "Str".to_string(),
             _ => panic!("Variable type unknown"),
-        },
+        };
+        paths.push_str(&format!(
+            "::aurora_hal::PathInfo {{ path: \"{path}\", value_type: ::aurora_hal::ValueType::{variant} }},\n"
+        ));
+        getters.push_str(&format!(
+            "\"{path}\" => Ok(::aurora_hal::Value::{variant}(::aurora_hal::GetterSetter::get(&self.{access}))),\n"
+        ));
+        id_getters.push_str(&format!(
+            "{index} => Some(::aurora_hal::Value::{variant}(::aurora_hal::GetterSetter::get(&self.{access}))),\n"
+        ));
+        setters.push_str(&format!(
+            "(\"{path}\", ::aurora_hal::Value::{variant}(v)) => ::aurora_hal::set!(self.{access}, v),\n"
+        ));
+    }
+
+    format!(
+        "impl ::aurora_hal::PathRegistry for {struct_name} {{
+            fn paths(&self) -> &'static [::aurora_hal::PathInfo] {{
+                &[{paths}]
+            }}
+
+            fn get_by_path(&self, path: &str) -> Result<::aurora_hal::Value, ::aurora_hal::PathError> {{
+                match path {{
+                    {getters}
+                    _ => Err(::aurora_hal::PathError::UnknownPath(path.to_string())),
+                }}
+            }}
+
+            fn get_by_id(&self, id: ::aurora_hal::LeafId) -> Option<::aurora_hal::Value> {{
+                match id.index() {{
+                    {id_getters}
+                    _ => None,
+                }}
+            }}
+
+            fn set_by_path(&self, path: &str, value: ::aurora_hal::Value) -> Result<(), ::aurora_hal::PathError> {{
+                match (path, value) {{
+                    {setters}
+                    (path, value) => {{
+                        let expected = self.value_type(path)?;
+                        return Err(::aurora_hal::PathError::TypeMismatch {{
+                            path: path.to_string(),
+                            expected,
+                            found: value.value_type(),
+                        }});
+                    }}
+                }}
+                Ok(())
+            }}
+        }}\n"
+    )
+}
+
+/// Generates `{struct_name}Snapshot`, a plain-data copy of the IoTree with the same nested layout,
+/// together with `snapshot()` and `restore()` for the IoTree struct.
+fn build_snapshot_impl(struct_name: &str, toml: &Value) -> String {
+    let mut defs = String::new();
+    let empty = Value::Table(Default::default());
+    let (process_read, process_restore) = build_snapshot(
+        "ProcessVar",
+        "self.process",
+        "snapshot.process",
+        toml.get("Process").unwrap_or(&empty),
+        &mut defs,
+    );
+    let (control_read, control_restore) = build_snapshot(
+        "ControlVar",
+        "self.control",
+        "snapshot.control",
+        toml.get("Control").unwrap_or(&empty),
+        &mut defs,
+    );
+
+    format!(
+        "{defs}
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct {struct_name}Snapshot {{
+            pub process: ProcessVarSnapshot,
+            pub control: ControlVarSnapshot,
+        }}
+
+        impl {struct_name} {{
+            /// Copies all values of the tree. No update runs while the copy is taken, so values
+            /// written in one `aurora_hal::update()` are always seen together. Fails with the
+            /// copy of the last attempt if updates kept overlapping with the copy.
+            pub fn snapshot(&self) -> Result<{struct_name}Snapshot, ::aurora_hal::Inconsistent<{struct_name}Snapshot>> {{
+                ::aurora_hal::read_consistent(|| {struct_name}Snapshot {{
+                    process: {process_read},
+                    control: {control_read},
+                }})
+            }}
+
+            /// Writes all values of the snapshot back as one update, ring buffers are refilled
+            /// with the values of the snapshot. Callbacks are not run.
+            pub fn restore(&self, snapshot: &{struct_name}Snapshot) {{
+                let _guard = ::aurora_hal::begin_update();
+                {process_restore}
+                {control_restore}
+            }}
+        }}\n"
+    )
+}
+
+/// Adds the definition of `{key}Snapshot` for the table `value` to `defs`. Returns an expression
+/// reading the snapshot from the struct at `access` and statements restoring it from `snapshot`.
+fn build_snapshot(
+    key: &str,
+    access: &str,
+    snapshot: &str,
+    value: &Value,
+    defs: &mut String,
+) -> (String, String) {
+    let mut fields = String::new();
+    let mut read = String::new();
+    let mut restore = String::new();
+
+    if let Value::Table(table) = value {
+        for (k, v) in table.iter() {
+            let var_type = match v {
+                Value::String(t) => Some((t.as_str(), 0)),
+                Value::Table(t) => match (t.get("type"), t.get("size")) {
+                    (Some(Value::String(t)), Some(Value::Integer(size))) => {
+                        Some((t.as_str(), *size))
+                    }
+                    // A variable with meta but without history
+                    (Some(Value::String(t)), None) => Some((t.as_str(), 0)),
+                    _ => None,
+                },
+                _ => continue,
+            };
+
+            match var_type {
+                Some((t, 0)) => {
+                    let rust_type = if t == "str" { "String" } else { t };
+                    fields.push_str(&format!("pub {k}: {rust_type},\n"));
+                    read.push_str(&format!(
+                        "{k}: ::aurora_hal::GetterSetter::get(&{access}.{k}),\n"
+                    ));
+                    restore.push_str(&format!(
+                        "::aurora_hal::GetterSetter::set(&{access}.{k}, {snapshot}.{k}.clone());\n"
+                    ));
+                }
+                Some((t, size)) => {
+                    fields.push_str(&format!("pub {k}: [{t}; {size}],\n"));
+                    read.push_str(&format!(
+                        "{k}: ::std::convert::TryInto::try_into(::aurora_hal::ArrayGetter::get_array(&{access}.{k})).unwrap(),\n"
+                    ));
+                    restore.push_str(&format!(
+                        "for v in {snapshot}.{k}.iter() {{ ::aurora_hal::GetterSetter::set(&{access}.{k}, *v); }}\n"
+                    ));
+                }
+                None => {
+                    let (sub_read, sub_restore) = build_snapshot(
+                        k,
+                        &format!("{access}.m_{k}"),
+                        &format!("{snapshot}.m_{k}"),
+                        v,
+                        defs,
+                    );
+                    fields.push_str(&format!("pub m_{k}: {k}Snapshot,\n"));
+                    read.push_str(&format!("m_{k}: {sub_read},\n"));
+                    restore.push_str(&sub_restore);
+                }
+            }
+        }
+    }
+
+    defs.push_str(&format!(
+        "#[derive(Clone, Debug, PartialEq)]\npub struct {key}Snapshot {{\n{fields}}}\n\n"
+    ));
+    (format!("{key}Snapshot {{\n{read}}}"), restore)
+}
+
+/// `path` is the dotted path of `value`, `ids` maps the paths of the variables to their `LeafId`.
+fn build_struct(
+    key: &str,
+    value: &Value,
+    path: &str,
+    ids: &HashMap<String, usize>,
+) -> (Option<String>, Option<String>) {
+    let mut struct_def = String::new();
+    let mut rest = String::new();
+
+    match value {
+        Value::String(var_type) => {
+            struct_def.push_str(&format!(
+                "{key}: Leaf<{}, {}>,\n",
+                scalar_type(var_type),
+                ids[path]
+            ));
+            (Some(struct_def), None)
+        }
         Value::Table(table) => {
             let mut type_name: Option<String> = None;
             let mut ringbuffer_size: Option<String> = None;
+            let mut meta = false;
             for (k, v) in table.iter() {
                 if k == "type" {
                     if let Value::String(t) = v {
@@ -105,21 +505,36 @@ fn build_struct(key: &str, value: &Value) -> (Option<String>, Option<String>) {
                         );
                     }
                 }
+                if k == "meta" {
+                    if let Value::Boolean(m) = v {
+                        meta = *m;
+                    } else {
+                        panic!(
+                            "The value of a 'meta' field in the struct config must be a Boolean"
+                        );
+                    }
+                }
+            }
+
+            // Variables with meta don't need a history
+            if meta && ringbuffer_size.is_none() {
+                ringbuffer_size = Some("0".to_string());
             }
 
             match (type_name, ringbuffer_size) {
                 (Some(t), Some(s)) => {
-                    if s == "0" {
-                        let res = format!("{key}: Atomic{},\n", t.as_str().to_uppercase());
-                        (Some(res), None)
+                    let var_type = if s == "0" {
+                        scalar_type(&t)
                     } else {
-                        let res = format!(
-                            "{key}: std::sync::RwLock<RingBuffer<{}, {}>>,\n",
-                            t.as_str(),
-                            s.as_str()
-                        );
-                        (Some(res), None)
-                    }
+                        format!("std::sync::RwLock<RingBuffer<{t}, {s}>>")
+                    };
+                    let var_type = if meta {
+                        format!("WithMeta<{var_type}>")
+                    } else {
+                        var_type
+                    };
+                    let res = format!("{key}: Leaf<{var_type}, {}>,\n", ids[path]);
+                    (Some(res), None)
                 }
                 (Some(_), None) => {
                     panic!("Size of history not specified");
@@ -131,7 +546,8 @@ fn build_struct(key: &str, value: &Value) -> (Option<String>, Option<String>) {
                     rest.push_str(&format!("#[derive(Init)]\nstruct {key} {{\n"));
 
                     for (k, v) in table.iter() {
-                        let (struct_res, sub_structs) = build_struct(k, v);
+                        let (struct_res, sub_structs) =
+                            build_struct(k, v, &format!("{path}.{k}"), ids);
                         if let Some(val) = struct_res {
                             rest.push_str(&val);
                         }
@@ -149,15 +565,36 @@ fn build_struct(key: &str, value: &Value) -> (Option<String>, Option<String>) {
     }
 }
 
+/// The field type of a variable without history, e.g. `AtomicF32` for `f32`.
+fn scalar_type(var_type: &str) -> String {
+    match var_type {
+        "u64" | "u32" | "u16" | "i64" | "i32" | "i16" | "f64" | "f32" => {
+            format!("Atomic{}", var_type.to_uppercase())
+        }
+        "bool" => "AtomicBool".to_string(),
+        "str" => "std::sync::RwLock<String>".to_string(),
+        _ => panic!("Variable type unknown"),
+    }
+}
+
+/// Generates `init_callbacks()` from a Callbacks.toml, e.g. `derive_callbacks!("Callbacks.toml")`.
+/// The path is relative to the `Cargo.toml` of the crate using the macro.
+/// Every entry has a `var`, a `condition` and a `callback`, and an optional `priority` (default 0,
+/// lower priorities run first).
+///
 /// # Panics
 ///
-/// Will panic if Callbacks.toml is unparseable
+/// Will panic if the Callbacks.toml is unparseable
 #[proc_macro]
-pub fn derive_callbacks(_input: TokenStream) -> TokenStream {
-    let toml = include_str!("Callbacks.toml");
+pub fn derive_callbacks(input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(input as syn::LitStr);
+    let (full_path, toml) = match read_config(&path) {
+        Ok(config) => config,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let toml = toml
         .parse::<Value>()
-        .expect("Couldn't parse Callbacks.toml");
+        .unwrap_or_else(|e| panic!("Couldn't parse {full_path}: {e}"));
 
     let mut callback_code = String::new();
 
@@ -168,11 +605,19 @@ pub fn derive_callbacks(_input: TokenStream) -> TokenStream {
                     let mut owning_variable = String::new();
                     let mut condition = String::new();
                     let mut callback = String::new();
+                    let mut priority = 0;
                     for (key, value) in callback_def {
                         match key.as_str() {
                             "var" => {
                                 if let Value::String(var) = value {
-                                    owning_variable.push_str(build_var_path(var).as_str());
+                                    owning_variable.push_str(&var_access(var));
+                                }
+                            }
+                            "priority" => {
+                                if let Value::Integer(p) = value {
+                                    priority = *p;
+                                } else {
+                                    panic!("The priority of a callback must be an Integer");
                                 }
                             }
                             "condition" => {
@@ -201,10 +646,9 @@ pub fn derive_callbacks(_input: TokenStream) -> TokenStream {
                         && (!condition.is_empty())
                         && (!callback.is_empty())
                     {
-                        callback_code.push_str(&format!("let mut v: Vec<(Box<dyn Fn() -> bool + Send + Sync>, Box<dyn Fn() + Send + Sync>)> = Vec::new();\n\
-                                                                v.push((Box::new(||{{{condition}}}), Box::new(||{{{callback}}})));\n\
-                                                                CALLBACKS.lock().unwrap().insert(\"{owning_variable}\".to_string(), v);\n"));
-                        // println!("Callback Code: {}", callback_code);
+                        callback_code.push_str(&format!(
+                            "::aurora_hal::register_callback_with_priority({owning_variable}.id(), {priority}, || ::aurora_hal::expr::holds(|| Some({condition})), || ::aurora_hal::expr::run(|| {{ {callback} Some(()) }}));\n"
+                        ));
                     } else {
                         panic!("Callback is missing either an owning variable, a condition or a callback function");
                     }
@@ -219,35 +663,31 @@ pub fn derive_callbacks(_input: TokenStream) -> TokenStream {
     let cb_tokens: proc_macro2::TokenStream = callback_code.parse().unwrap();
 
     quote! {
+        const _: &str = include_str!(#full_path);
+        // Registers the callbacks of the Callbacks.toml, only the first call has an effect
         pub fn init_callbacks() {
-            #cb_tokens
+            static INIT: ::std::sync::Once = ::std::sync::Once::new();
+            INIT.call_once(|| {
+                #cb_tokens
+            });
         }
     }
     .into()
 }
 
-fn build_var_path(s: &str) -> String {
-    let mut path = String::new();
-    if s.contains("process") {
-        path.push_str("CALLBACKS.lock().unwrap().process.");
-    } else if s.contains("control") {
-        path.push_str("CALLBACKS.lock().unwrap().control.");
-    } else {
+/// The variable in the global `IOTREE` for a dotted path, e.g. `IOTREE.process.m_Sensor1.pressure`
+/// for `process.Sensor1.pressure`. Callbacks are registered under its `LeafId`.
+fn var_access(s: &str) -> String {
+    if !(s.starts_with("process.") || s.starts_with("control.")) {
         panic!("Trying to access non-Process and non-Control variable")
     }
-    let mut members = s.split('.').peekable();
-    members.next();
-
-    while let Some(member) = members.next() {
-        if members.peek().is_some() {
-            path.push_str("m_");
-            path.push_str(member);
-            path.push('.');
-        } else {
-            path.push_str(member);
-        }
+    let mut members: Vec<&str> = s.split('.').collect();
+    let var = members.pop().unwrap();
+    let mut access = format!("IOTREE.{}", members[0]);
+    for table in &members[1..] {
+        access.push_str(&format!(".m_{table}"));
     }
-    path
+    format!("{access}.{var}")
 }
 
 /// # Panics
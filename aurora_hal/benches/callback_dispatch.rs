@@ -0,0 +1,98 @@
+// Compares the callback dispatch of set! with the global mutex it used before: cargo bench -p aurora_hal
+// Each benchmark dispatches the callbacks of a variable from 1 and 4 threads, once for a variable with a callback
+// whose condition doesn't hold (the common case of a sensor write) and once for a variable without callbacks.
+
+use aurora_hal::{Callback, CallbackRegistry, Condition, LeafId};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u64 = 1_000_000;
+
+static VALUE: AtomicU64 = AtomicU64::new(0);
+
+// The dispatch of set! with CALLBACKS being a mutex keyed by the stringified path: one lock to check for callbacks
+// and one to run them
+struct MutexDispatch(Mutex<HashMap<String, Vec<(Condition, Callback)>>>);
+
+impl MutexDispatch {
+    fn run(&self, path: &str) {
+        if self.0.lock().unwrap().deref().contains_key(path) {
+            for cb in self.0.lock().unwrap().get(path).unwrap() {
+                if cb.0.deref()() {
+                    cb.1.deref()();
+                }
+            }
+        }
+    }
+}
+
+fn condition() -> bool {
+    VALUE.load(Ordering::Relaxed) > u64::MAX / 2
+}
+
+fn callback() {
+    VALUE.store(0, Ordering::Relaxed);
+}
+
+// Runs dispatch ITERATIONS times on each of the threads and returns the mean time per call
+fn measure(threads: usize, dispatch: impl Fn() + Send + Sync + 'static) -> Duration {
+    let dispatch = Arc::new(dispatch);
+    let start = Instant::now();
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let dispatch = dispatch.clone();
+            thread::spawn(move || {
+                for i in 0..ITERATIONS {
+                    VALUE.store(black_box(i), Ordering::Relaxed);
+                    dispatch();
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    start.elapsed() / (ITERATIONS * threads as u64) as u32
+}
+
+fn main() {
+    let mutex = Arc::new(MutexDispatch(Mutex::new(HashMap::new())));
+    mutex.0.lock().unwrap().insert(
+        "IOTREE.process.m_Sensor1.pressure".to_string(),
+        vec![(Box::new(condition), Box::new(callback))],
+    );
+    let registry = Arc::new(CallbackRegistry::new());
+    registry.register(LeafId(0), 0, condition, callback);
+
+    println!("{:<40}{:>12}{:>12}", "", "mutex", "registry");
+    for threads in [1, 4] {
+        for (name, path, leaf) in [
+            (
+                "with callback",
+                "IOTREE.process.m_Sensor1.pressure",
+                LeafId(0),
+            ),
+            (
+                "without callbacks",
+                "IOTREE.process.m_Sensor1.temp",
+                LeafId(1),
+            ),
+        ] {
+            let m = mutex.clone();
+            let with_mutex = measure(threads, move || m.run(black_box(path)));
+            let r = registry.clone();
+            let with_registry = measure(threads, move || r.run(black_box(leaf)));
+            println!(
+                "{:<40}{:>12?}{:>12?}",
+                format!("{name}, {threads} thread(s)"),
+                with_mutex,
+                with_registry
+            );
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// Condition/action callbacks of IoTree variables, run by set! after the variable was written.
+// Any number of callbacks can be registered for a variable, at startup by the init_callbacks function generated from the
+// Callbacks.toml or at runtime with register_callback. They run by ascending priority, callbacks with the same priority
+// in the order they were registered. The returned CallbackId removes a callback again.
+// Callbacks belong to the LeafId of their variable, e.g. IOTREE.process.m_Sensor1.pressure.id() or
+// IOTREE.leaf_id("process.Sensor1.pressure").
+//
+// set! runs on every sensor write, so dispatching doesn't take a lock: the callbacks live in a table which registering
+// replaces as a whole (see the rcu module). Since nothing is locked while callbacks run, they may call set! and register
+// or unregister callbacks themselves. Callbacks setting variables which have callbacks again are nested at most
+// MAX_CALLBACK_DEPTH deep, the callbacks of deeper writes are skipped and counted.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::rcu::Rcu;
+use crate::LeafId;
+
+pub type Callback = Box<dyn Fn() + Send + Sync>;
+pub type Condition = Box<dyn Fn() -> bool + Send + Sync>;
+
+pub const MAX_CALLBACK_DEPTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+pub struct CallbackEntry {
+    pub id: CallbackId,
+    pub priority: i32,
+    pub condition: Condition,
+    pub callback: Callback,
+}
+
+// Callback chains of all variables indexed by their LeafId, sorted by priority
+type Table = Vec<Vec<Arc<CallbackEntry>>>;
+
+pub struct CallbackRegistry {
+    table: Rcu<Table>,
+    next_id: AtomicU64,
+    skipped_runs: AtomicU64,
+}
+
+pub static CALLBACKS: CallbackRegistry = CallbackRegistry::new();
+
+thread_local! {
+    // How many run() calls of this thread are running callbacks
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+struct DepthGuard;
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+impl CallbackRegistry {
+    pub const fn new() -> CallbackRegistry {
+        CallbackRegistry {
+            table: Rcu::new(),
+            next_id: AtomicU64::new(0),
+            skipped_runs: AtomicU64::new(0),
+        }
+    }
+
+    // Callbacks with a lower priority run first, callbacks with the same priority in the order they were registered
+    pub fn register(
+        &self,
+        leaf: LeafId,
+        priority: i32,
+        condition: impl Fn() -> bool + Send + Sync + 'static,
+        callback: impl Fn() + Send + Sync + 'static,
+    ) -> CallbackId {
+        let id = CallbackId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let entry = Arc::new(CallbackEntry {
+            id,
+            priority,
+            condition: Box::new(condition),
+            callback: Box::new(callback),
+        });
+        self.table.modify(|table| {
+            if table.len() <= leaf.index() {
+                table.resize_with(leaf.index() + 1, Vec::new);
+            }
+            let chain = &mut table[leaf.index()];
+            // Behind all callbacks with the same priority
+            let pos = chain.partition_point(|e| e.priority <= priority);
+            chain.insert(pos, entry);
+        });
+        id
+    }
+
+    // Returns false if the callback was already unregistered
+    pub fn unregister(&self, id: CallbackId) -> bool {
+        self.table
+            .try_modify(|table| {
+                let chain = table
+                    .iter_mut()
+                    .find(|chain| chain.iter().any(|entry| entry.id == id))?;
+                chain.retain(|entry| entry.id != id);
+                Some(())
+            })
+            .is_some()
+    }
+
+    // Runs the callbacks of the variable whose condition holds, used by set!
+    pub fn run(&self, leaf: LeafId) {
+        if DEPTH.with(|depth| depth.get()) >= MAX_CALLBACK_DEPTH {
+            self.skipped_runs.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.table.read(|table| {
+            let Some(chain) = table.and_then(|table| table.get(leaf.index())) else {
+                return;
+            };
+            if chain.is_empty() {
+                return;
+            }
+            DEPTH.with(|depth| depth.set(depth.get() + 1));
+            let _depth = DepthGuard;
+            for entry in chain {
+                if (entry.condition)() {
+                    (entry.callback)();
+                }
+            }
+        })
+    }
+
+    // The callbacks of the variable in the order they run
+    pub fn callbacks(&self, leaf: LeafId) -> Vec<Arc<CallbackEntry>> {
+        self.table.read(|table| {
+            table
+                .and_then(|table| table.get(leaf.index()))
+                .cloned()
+                .unwrap_or_default()
+        })
+    }
+
+    // Number of run() calls whose callbacks were skipped because they were nested too deep
+    pub fn skipped_runs(&self) -> u64 {
+        self.skipped_runs.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for CallbackRegistry {
+    fn default() -> Self {
+        CallbackRegistry::new()
+    }
+}
+
+// Registers a callback with priority 0, which runs after the callbacks registered before it
+pub fn register_callback(
+    leaf: LeafId,
+    condition: impl Fn() -> bool + Send + Sync + 'static,
+    callback: impl Fn() + Send + Sync + 'static,
+) -> CallbackId {
+    CALLBACKS.register(leaf, 0, condition, callback)
+}
+
+// Callbacks with a lower priority run first
+pub fn register_callback_with_priority(
+    leaf: LeafId,
+    priority: i32,
+    condition: impl Fn() -> bool + Send + Sync + 'static,
+    callback: impl Fn() + Send + Sync + 'static,
+) -> CallbackId {
+    CALLBACKS.register(leaf, priority, condition, callback)
+}
+
+// Returns false if the callback was already unregistered
+pub fn unregister_callback(id: CallbackId) -> bool {
+    CALLBACKS.unregister(id)
+}
+
+pub fn run_callbacks(leaf: LeafId) {
+    CALLBACKS.run(leaf)
+}
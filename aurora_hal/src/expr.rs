@@ -0,0 +1,145 @@
+// Runtime support for the conditions and callbacks of the Callbacks.toml, which derive_callbacks compiles to Rust.
+// Numbers in expressions are f64 whatever the type of the variable, so that e.g. sqrt(process.Sensor1.pressure) or
+// process.Sensor2.y_acc / 2 behave the same for every variable. Assigned values are converted back to the type of the
+// variable, which truncates and saturates for integers like `as`.
+//
+// Functions: abs(x), sqrt(x), min(a, b), max(a, b), clamp(x, low, high)
+// Ring buffers: mean(var), slope(var) (change per sample, least squares), optionally over the newest n values only with
+// mean(var, n) and slope(var, n), and last(var, n), the n-th newest value (last(var, 1) is the newest)
+// mean, slope and last have no value for an empty window, e.g. mean(var, 0). An expression using one of them has no value then
+// either: a condition does not hold, no matter whether it is negated, and a callback does nothing.
+// Variables with meta: time_since_set(var) in seconds and update_count(var)
+
+// The value of a variable in an expression
+pub trait Operand {
+    type Value;
+    fn operand(self) -> Self::Value;
+}
+
+// Numbers stored in ring buffers
+pub trait Number: Copy {
+    fn to_f64(self) -> f64;
+}
+
+// Converts the result of an expression to the type of the assigned variable
+pub trait Cast<T> {
+    fn cast(self) -> T;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Operand for $t {
+                type Value = f64;
+                fn operand(self) -> f64 {
+                    self as f64
+                }
+            }
+
+            impl Number for $t {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+
+            impl Cast<$t> for f64 {
+                fn cast(self) -> $t {
+                    self as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u64, u32, u16, i64, i32, i16, f64, f32);
+
+impl Operand for bool {
+    type Value = bool;
+    fn operand(self) -> bool {
+        self
+    }
+}
+
+impl Cast<bool> for bool {
+    fn cast(self) -> bool {
+        self
+    }
+}
+
+pub fn abs(x: f64) -> f64 {
+    x.abs()
+}
+
+pub fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+pub fn min(a: f64, b: f64) -> f64 {
+    a.min(b)
+}
+
+pub fn max(a: f64, b: f64) -> f64 {
+    a.max(b)
+}
+
+// Doesn't panic like f64::clamp if low > high, the result is high then
+pub fn clamp(x: f64, low: f64, high: f64) -> f64 {
+    x.max(low).min(high)
+}
+
+// The newest n values of a ring buffer (oldest first, like get_array), all of them for None
+fn window<T: Number>(values: &[T], n: Option<f64>) -> &[T] {
+    match n {
+        Some(n) => {
+            let n = (n.max(0.0) as usize).min(values.len());
+            &values[values.len() - n..]
+        }
+        None => values,
+    }
+}
+
+// None for an empty window
+pub fn mean<T: Number>(values: &[T], n: Option<f64>) -> Option<f64> {
+    let values = window(values, n);
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().map(|v| v.to_f64()).sum::<f64>() / values.len() as f64)
+}
+
+// Least squares slope per sample, None for an empty window and 0 for a single value
+pub fn slope<T: Number>(values: &[T], n: Option<f64>) -> Option<f64> {
+    let values = window(values, n);
+    let mean_y = mean(values, None)?;
+    if values.len() < 2 {
+        return Some(0.0);
+    }
+    let count = values.len() as f64;
+    let mean_x = (count - 1.0) / 2.0;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, y) in values.iter().enumerate() {
+        let dx = x as f64 - mean_x;
+        covariance += dx * (y.to_f64() - mean_y);
+        variance += dx * dx;
+    }
+    Some(covariance / variance)
+}
+
+// The n-th newest value, last(values, 1) is the newest. Clamped to the oldest and newest value, None without values.
+pub fn last<T: Number>(values: &[T], n: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let n = (n.max(1.0) as usize).min(values.len());
+    Some(values[values.len() - n].to_f64())
+}
+
+// Runs a compiled condition, which has no value if it uses mean or slope of an empty window
+pub fn holds(condition: impl FnOnce() -> Option<bool>) -> bool {
+    condition().unwrap_or(false)
+}
+
+// Runs a compiled callback, which stops at the first expression without a value
+pub fn run(callback: impl FnOnce() -> Option<()>) {
+    let _ = callback();
+}
@@ -0,0 +1,83 @@
+// Every variable of an IoTree is a Leaf, which carries the identifier of the variable in its type. add_fields numbers the
+// variables in the order of PathRegistry::paths(), so the identifier of "process.Sensor1.pressure" is its index there.
+// set! and the callbacks of the Callbacks.toml both use this identifier, which makes the lookup of the callbacks of a
+// variable independent of how its path is spelled.
+// Identifiers are only unique within an IoTree, while CALLBACKS and the subscriptions are global and keyed by them, so a
+// binary must only define a single tree with init_io_tree!. aurora_hal itself defines none, test binaries define their own.
+// Leaf derefs to the wrapped type, e.g. to call get_with_meta() on variables with meta.
+
+use crate::{ArrayGetter, GetterSetter};
+use std::fmt;
+use std::ops::Deref;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LeafId(pub u32);
+
+impl LeafId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for LeafId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+pub struct Leaf<T, const ID: u32> {
+    value: T,
+}
+
+impl<T, const ID: u32> Leaf<T, ID> {
+    pub const ID: LeafId = LeafId(ID);
+
+    // For variables outside of an IoTree, e.g. statics in tests
+    pub const fn with_value(value: T) -> Leaf<T, ID> {
+        Leaf { value }
+    }
+
+    pub fn id(&self) -> LeafId {
+        Self::ID
+    }
+}
+
+impl<T: Default, const ID: u32> Leaf<T, ID> {
+    pub fn new() -> Leaf<T, ID> {
+        Leaf::with_value(T::default())
+    }
+}
+
+impl<T: Default, const ID: u32> Default for Leaf<T, ID> {
+    fn default() -> Self {
+        Leaf::new()
+    }
+}
+
+impl<T, const ID: u32> Deref for Leaf<T, ID> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: GetterSetter, const ID: u32> GetterSetter for Leaf<T, ID> {
+    type InnerType = T::InnerType;
+
+    fn set(&self, val: Self::InnerType) {
+        self.value.set(val);
+    }
+
+    fn get(&self) -> Self::InnerType {
+        self.value.get()
+    }
+}
+
+impl<T: ArrayGetter, const ID: u32> ArrayGetter for Leaf<T, ID> {
+    type InnerType = T::InnerType;
+
+    fn get_array(&self) -> Vec<Self::InnerType> {
+        self.value.get_array()
+    }
+}
@@ -4,20 +4,37 @@ extern crate cfg_if;
 #[macro_use]
 extern crate lazy_static;
 
+// Lets the code generated by aurora_hal_macros refer to ::aurora_hal inside this crate as well
+extern crate self as aurora_hal;
+
 mod atomic_traits;
+mod callbacks;
+pub mod expr;
+mod leaf;
+pub mod logger;
+mod meta;
+mod rcu;
+mod registry;
+mod seqlock;
+pub mod subscriptions;
+pub mod telemetry;
 
 use atomic::{AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicU16, AtomicU32, AtomicU64};
-use atomic_float::{AtomicF32, AtomicF64};
 use atomic_traits::Atomic;
-use aurora_hal_macros::{add_fields, derive_callbacks, Init};
-use std::collections::HashMap;
 use std::ops::Deref;
-use std::string::ToString;
-use std::sync::Mutex;
 use std::sync::{atomic, RwLock};
 
-type Callback = Box<dyn Fn() + Send + Sync>;
-type Condition = Box<dyn Fn() -> bool + Send + Sync>;
+pub use callbacks::{
+    register_callback, register_callback_with_priority, run_callbacks, unregister_callback,
+    Callback, CallbackEntry, CallbackId, CallbackRegistry, Condition, CALLBACKS,
+    MAX_CALLBACK_DEPTH,
+};
+pub use leaf::{Leaf, LeafId};
+pub use meta::{Meta, WithMeta};
+pub use registry::{PathError, PathInfo, PathRegistry, Value, ValueType};
+pub use seqlock::{
+    begin_update, read_consistent, update, Inconsistent, UpdateGuard, MAX_READ_ATTEMPTS,
+};
 
 pub struct RingBuffer<T, const N: usize> {
     buf: [T; N],
@@ -121,46 +138,59 @@ impl<T: Copy, const N: usize> ArrayGetter for RwLock<RingBuffer<T, N>> {
     }
 }
 
-// These Structs are global. Callback chains are stored in the CALLBACKS struct, and the IOTREE struct is the data center for all data used during flight
+// Items the code generated by init_io_tree! refers to, they are imported at the call site
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::{GetterSetter, Leaf, RingBuffer, WithMeta};
+    pub use atomic_float::{AtomicF32, AtomicF64};
+    pub use aurora_hal_macros::{add_fields, derive_callbacks, Init};
+    pub use lazy_static::lazy_static;
+    pub use std::sync::atomic::{
+        AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicU16, AtomicU32, AtomicU64,
+    };
+}
+
+// ============================ MACROS ==========================================================
 
+// Defines the IoTree struct, the global IOTREE and the init_callbacks function in the calling module.
+// Call it once per binary, the leaf ids of different trees overlap while CALLBACKS and the subscriptions are global.
+// aurora_hal does not define a tree itself, its IoTree.toml and Callbacks.toml are an example used by tests/example_tests.rs.
+// Both paths are relative to the Cargo.toml of the calling crate, which is rebuilt when one of the files changes:
+//     init_io_tree!("IoTree.toml", "Callbacks.toml");
 // Three macros are associated with the IoTree.
 // add_fields parses the IoTree.toml file and builds the nested structure of the IoTree struct.
 // derive(Init) derives an initialization function for the IoTree struct
-#[add_fields]
-#[derive(Init)]
-pub struct IoTree {}
-
-lazy_static! {
-    pub static ref CALLBACKS: Mutex<HashMap<String, Vec<(Condition, Callback)>>> = {
-        let v: Vec<(Condition, Callback)> = Vec::new();
-        Mutex::new(HashMap::from([("empty".to_string(), v)]))
-    };
-}
+// derive_callbacks generates the init_callbacks function, which registers all callbacks defined in the Callbacks.toml file
+#[macro_export]
+macro_rules! init_io_tree {
+    ( $io_tree:literal, $callbacks:literal ) => {
+        #[allow(unused_imports)]
+        use $crate::__private::*;
 
-lazy_static! {
-    pub static ref IOTREE: IoTree = IoTree::new();
-}
+        #[add_fields($io_tree)]
+        #[derive(Init)]
+        pub struct IoTree {}
 
-// Generate the init_callbacks function, which adds all callbacks defined in the Callbacks.toml file to the CALLBACKS HashMap
-derive_callbacks!();
+        lazy_static! {
+            pub static ref IOTREE: IoTree = IoTree::new();
+        }
 
-// ============================ MACROS ==========================================================
+        derive_callbacks!($callbacks);
+    };
+}
 
+// Sets a variable of the IoTree, notifies its subscribers and runs its callbacks:
+//     set!(IOTREE.process.m_Sensor1.pressure, 3)
 #[macro_export]
 macro_rules! set {
-    ( $path:expr, $val:expr ) => {
-        $path.set($val);
-        if CALLBACKS
-            .lock()
-            .unwrap()
-            .deref()
-            .contains_key(stringify!($path))
-        {
-            for cb in CALLBACKS.lock().unwrap().get(stringify!($path)).unwrap() {
-                if cb.0.deref()() == true {
-                    cb.1.deref()();
-                }
-            }
+    ( $path:expr, $val:expr ) => {{
+        let leaf = &$path;
+        let id = leaf.id();
+        let old = $crate::subscriptions::is_subscribed(id).then(|| $crate::GetterSetter::get(leaf));
+        $crate::GetterSetter::set(leaf, $val);
+        if let Some(old) = old {
+            $crate::subscriptions::notify(id, old.into(), $crate::GetterSetter::get(leaf).into());
         }
-    };
+        $crate::run_callbacks(id);
+    }};
 }
@@ -0,0 +1,440 @@
+// Onboard flight data logger, appends timestamped records of all IoTree variables to log files.
+//
+// A log file starts with a header describing its schema, so it can be read without the IoTree.toml of the flight:
+// - the magic bytes "AURLOG" and the log format version (1 byte)
+// - the wall clock time the logger was started, in microseconds since the UNIX epoch (u64)
+// - the length of the schema (u32) followed by one "path:type\n" line per variable
+// Each record consists of
+// - its length (u32), counting the timestamp and the frame
+// - the time since the logger was started in microseconds (u64)
+// - a telemetry frame of all variables, see aurora_hal::telemetry
+// - a CRC-32 of the timestamp and the frame (u32)
+// All integers are little endian.
+//
+// Records are only ever appended, and the file is synced every few records. A record torn by a power loss fails its CRC
+// check, the reader stops there and all earlier records stay readable. Files can be preallocated, which leaves zeros
+// behind the last record, read as the end of the log. When a file is full, the logger continues in the next file
+// ("flight_0000.aurlog", "flight_0001.aurlog", ...).
+//
+// Records are written periodically by spawn_periodic(), or whenever Logger::record() is called, e.g. from a callback to
+// log on every set! of a variable.
+
+use crate::telemetry::{Schema, SchemaField, TelemetryError};
+use crate::{PathRegistry, ValueType};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const MAGIC: &[u8; 6] = b"AURLOG";
+pub const LOG_VERSION: u8 = 1;
+pub const FILE_EXTENSION: &str = "aurlog";
+
+#[derive(Debug)]
+pub enum LogError {
+    Io(io::Error),
+    Format(String),
+    Telemetry(TelemetryError),
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogError::Io(e) => write!(f, "log file error: {e}"),
+            LogError::Format(e) => write!(f, "invalid log file: {e}"),
+            LogError::Telemetry(e) => write!(f, "invalid log record: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LogError {}
+
+impl From<io::Error> for LogError {
+    fn from(e: io::Error) -> Self {
+        LogError::Io(e)
+    }
+}
+
+impl From<TelemetryError> for LogError {
+    fn from(e: TelemetryError) -> Self {
+        LogError::Telemetry(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoggerConfig {
+    pub directory: PathBuf,
+    // Files are named "{prefix}_{index:04}.aurlog"
+    pub prefix: String,
+    // A new file is started before a record would make the file larger than this
+    pub max_file_size: u64,
+    // Every file is extended to this size when it is created, 0 disables preallocation
+    pub preallocate: u64,
+    // The file is synced to the storage after this many records
+    pub sync_every: u32,
+}
+
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        LoggerConfig {
+            directory: PathBuf::from("logs"),
+            prefix: "flight".to_string(),
+            max_file_size: 64 * 1024 * 1024,
+            preallocate: 0,
+            sync_every: 10,
+        }
+    }
+}
+
+impl LoggerConfig {
+    pub fn file_path(&self, index: u32) -> PathBuf {
+        self.directory
+            .join(format!("{}_{index:04}.{FILE_EXTENSION}", self.prefix))
+    }
+
+    // All existing log files of this config, in the order they were written
+    pub fn files(&self) -> Vec<PathBuf> {
+        (0..)
+            .map(|index| self.file_path(index))
+            .take_while(|path| path.exists())
+            .collect()
+    }
+}
+
+// CRC-32 (IEEE 802.3)
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+pub struct Logger {
+    config: LoggerConfig,
+    schema: Schema,
+    file: File,
+    file_index: u32,
+    write_pos: u64,
+    start: Instant,
+    start_unix_us: u64,
+    unsynced: u32,
+}
+
+impl Logger {
+    // Starts a new file after the existing files of the config, so logs of an earlier boot are never overwritten
+    pub fn create(config: LoggerConfig, tree: &impl PathRegistry) -> Result<Logger, LogError> {
+        fs::create_dir_all(&config.directory)?;
+        let file_index = config.files().len() as u32;
+        let start_unix_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_micros() as u64);
+        let schema = Schema::of(tree);
+        let (file, write_pos) = create_file(&config, file_index, &schema, start_unix_us)?;
+        Ok(Logger {
+            config,
+            schema,
+            file,
+            file_index,
+            write_pos,
+            start: Instant::now(),
+            start_unix_us,
+            unsynced: 0,
+        })
+    }
+
+    pub fn current_file(&self) -> PathBuf {
+        self.config.file_path(self.file_index)
+    }
+
+    // Appends a record of the current values of the tree
+    pub fn record(&mut self, tree: &impl PathRegistry) -> Result<(), LogError> {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        let frame = self.schema.encode(tree)?;
+
+        let mut record = Vec::with_capacity(16 + frame.len());
+        record.extend_from_slice(&(8 + frame.len() as u32).to_le_bytes());
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record.extend_from_slice(&frame);
+        let crc = crc32(&record[4..]);
+        record.extend_from_slice(&crc.to_le_bytes());
+
+        if self.write_pos + record.len() as u64 > self.config.max_file_size {
+            self.rotate()?;
+        }
+        self.file.seek(SeekFrom::Start(self.write_pos))?;
+        self.file.write_all(&record)?;
+        self.write_pos += record.len() as u64;
+
+        self.unsynced += 1;
+        if self.unsynced >= self.config.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.unsynced = 0;
+        self.file.sync_data()
+    }
+
+    fn rotate(&mut self) -> Result<(), LogError> {
+        self.sync()?;
+        let (file, write_pos) = create_file(
+            &self.config,
+            self.file_index + 1,
+            &self.schema,
+            self.start_unix_us,
+        )?;
+        self.file = file;
+        self.file_index += 1;
+        self.write_pos = write_pos;
+        Ok(())
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        // Nobody is left to report the error to, the records up to the last sync are safe anyway
+        let _ = self.sync();
+    }
+}
+
+// Creates a log file and writes its header, returns the file and the position of the first record
+fn create_file(
+    config: &LoggerConfig,
+    index: u32,
+    schema: &Schema,
+    start_unix_us: u64,
+) -> Result<(File, u64), LogError> {
+    let mut schema_text = String::new();
+    for field in schema.fields() {
+        schema_text.push_str(&format!("{}:{}\n", field.path, field.value_type));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.push(LOG_VERSION);
+    header.extend_from_slice(&start_unix_us.to_le_bytes());
+    header.extend_from_slice(&(schema_text.len() as u32).to_le_bytes());
+    header.extend_from_slice(schema_text.as_bytes());
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(config.file_path(index))?;
+    if config.preallocate > 0 {
+        file.set_len(config.preallocate)?;
+    }
+    file.write_all(&header)?;
+    file.sync_all()?;
+    Ok((file, header.len() as u64))
+}
+
+// Records the tree every interval on a separate thread
+pub fn spawn_periodic<T: PathRegistry + Sync>(
+    mut logger: Logger,
+    tree: &'static T,
+    interval: Duration,
+) -> LoggerHandle {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let thread_stop_flag = stop_flag.clone();
+    let thread = thread::spawn(move || {
+        let mut next = Instant::now();
+        while !thread_stop_flag.load(Ordering::Acquire) {
+            logger.record(tree)?;
+            next += interval;
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        logger.sync()?;
+        Ok(())
+    });
+    LoggerHandle { stop_flag, thread }
+}
+
+pub struct LoggerHandle {
+    stop_flag: Arc<AtomicBool>,
+    thread: JoinHandle<Result<(), LogError>>,
+}
+
+impl LoggerHandle {
+    // Returns true if the logger stopped because of an error
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // Stops the logger after the current record, returns the error that stopped it early
+    pub fn stop(self) -> Result<(), LogError> {
+        self.stop_flag.store(true, Ordering::Release);
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(LogError::Format("logger thread panicked".to_string())))
+    }
+}
+
+pub struct LogRecord {
+    // Time since the logger was started
+    pub timestamp: Duration,
+    pub values: Vec<crate::Value>,
+}
+
+pub struct LogReader {
+    schema: Schema,
+    start_unix_us: u64,
+    data: Vec<u8>,
+    pos: usize,
+    torn: bool,
+}
+
+impl LogReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<LogReader, LogError> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<LogReader, LogError> {
+        let invalid = |reason: &str| LogError::Format(reason.to_string());
+        if data.len() < 19 || &data[..6] != MAGIC {
+            return Err(invalid("not a log file"));
+        }
+        if data[6] != LOG_VERSION {
+            return Err(LogError::Format(format!(
+                "log format version {} is not supported",
+                data[6]
+            )));
+        }
+        let start_unix_us = u64::from_le_bytes(data[7..15].try_into().unwrap());
+        let schema_len = u32::from_le_bytes(data[15..19].try_into().unwrap()) as usize;
+        let schema_text = data
+            .get(19..19 + schema_len)
+            .and_then(|text| std::str::from_utf8(text).ok())
+            .ok_or_else(|| invalid("damaged schema"))?;
+
+        let mut fields = Vec::new();
+        for line in schema_text.lines() {
+            let (path, type_name) = line
+                .rsplit_once(':')
+                .ok_or_else(|| invalid("damaged schema"))?;
+            let value_type =
+                ValueType::from_toml_name(type_name).ok_or_else(|| invalid("damaged schema"))?;
+            fields.push(SchemaField {
+                path: path.to_string(),
+                value_type,
+            });
+        }
+
+        Ok(LogReader {
+            schema: Schema::new(fields),
+            start_unix_us,
+            data,
+            pos: 19 + schema_len,
+            torn: false,
+        })
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn start_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.start_unix_us)
+    }
+
+    // Returns true if reading stopped at a record damaged by a power loss, rather than at the end of the log
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+
+    // Returns the next record, None at the end of the log or at a damaged record
+    pub fn next_record(&mut self) -> Option<LogRecord> {
+        let rest = &self.data[self.pos..];
+        let len = match rest.get(..4) {
+            Some(len) => u32::from_le_bytes(len.try_into().unwrap()) as usize,
+            None => {
+                self.torn = !rest.is_empty();
+                return None;
+            }
+        };
+        // Preallocated space is zeroed
+        if len == 0 {
+            self.torn = rest.iter().any(|&byte| byte != 0);
+            return None;
+        }
+
+        let record = rest.get(4..4 + len + 4).filter(|record| {
+            let crc = u32::from_le_bytes(record[len..].try_into().unwrap());
+            len >= 8 && crc32(&record[..len]) == crc
+        });
+        let values = record.and_then(|record| self.schema.decode(&record[8..len]).ok());
+        let (Some(record), Some(values)) = (record, values) else {
+            self.torn = true;
+            return None;
+        };
+
+        let timestamp = Duration::from_micros(u64::from_le_bytes(record[..8].try_into().unwrap()));
+        let values = values.into_iter().map(|(_, value)| value).collect();
+        self.pos += 4 + len + 4;
+        Some(LogRecord { timestamp, values })
+    }
+
+    // Writes the header row and one row per record, the first column is the time since the logger was started in seconds
+    pub fn write_csv(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let mut header = vec!["time".to_string()];
+        header.extend(
+            self.schema
+                .fields()
+                .iter()
+                .map(|field| csv_escape(&field.path)),
+        );
+        writeln!(out, "{}", header.join(","))?;
+
+        while let Some(record) = self.next_record() {
+            let mut row = vec![format!("{:.6}", record.timestamp.as_secs_f64())];
+            row.extend(
+                record
+                    .values
+                    .iter()
+                    .map(|value| csv_escape(&value.to_string())),
+            );
+            writeln!(out, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Converts all log files to a single CSV file, e.g. all files of LoggerConfig::files()
+pub fn logs_to_csv(logs: &[PathBuf], csv: impl AsRef<Path>) -> Result<(), LogError> {
+    let mut out = io::BufWriter::new(File::create(csv)?);
+    let mut header_written = false;
+    for log in logs {
+        let mut reader = LogReader::open(log)?;
+        let mut rows = Vec::new();
+        reader.write_csv(&mut rows)?;
+        // Rotated files repeat the header row
+        let skip = if header_written {
+            rows.iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(rows.len(), |i| i + 1)
+        } else {
+            0
+        };
+        out.write_all(&rows[skip..])?;
+        header_written = true;
+    }
+    out.flush()?;
+    Ok(())
+}
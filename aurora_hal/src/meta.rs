@@ -0,0 +1,144 @@
+// Opt-in bookkeeping of when and how often an IoTree variable was set, so stale sensor data can be detected.
+// Variables declared with meta = true in the IoTree.toml are wrapped in WithMeta, which records the monotonic time of the
+// last set and counts the updates:
+//     altitude = { type = "f32", meta = true }
+//     acc = { type = "f64", size = 4, meta = true }
+// WithMeta implements GetterSetter and ArrayGetter like the wrapped type, so set!, the registry and snapshots work as before.
+// In the Callbacks.toml, time_since_set(var) gives the seconds since the last set and update_count(var) the number of sets.
+
+use crate::{ArrayGetter, GetterSetter, Inconsistent, RingBuffer, MAX_READ_ATTEMPTS};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    // Timestamps are stored as nanoseconds since this instant, plus one so that 0 means "never set"
+    static ref EPOCH: Instant = Instant::now();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Meta {
+    // None if the variable was never set
+    pub updated: Option<Instant>,
+    pub updates: u64,
+}
+
+impl Meta {
+    pub fn age(&self) -> Option<Duration> {
+        self.updated.map(|updated| updated.elapsed())
+    }
+
+    // Seconds since the last set, infinite if the variable was never set so that "older than" checks hold
+    pub fn age_secs(&self) -> f64 {
+        self.age().map_or(f64::INFINITY, |age| age.as_secs_f64())
+    }
+
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age().is_none_or(|age| age > max_age)
+    }
+}
+
+pub struct WithMeta<T> {
+    value: T,
+    updated: AtomicU64,
+    updates: AtomicU64,
+    // Number of ongoing sets, the same scheme as the seqlock module uses for updates of the whole tree
+    writers: AtomicUsize,
+}
+
+impl<T: Default> WithMeta<T> {
+    pub fn new() -> WithMeta<T> {
+        // Make sure the epoch lies before the first update
+        lazy_static::initialize(&EPOCH);
+        WithMeta {
+            value: T::default(),
+            updated: AtomicU64::new(0),
+            updates: AtomicU64::new(0),
+            writers: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: Default> Default for WithMeta<T> {
+    fn default() -> Self {
+        WithMeta::new()
+    }
+}
+
+impl<T> WithMeta<T> {
+    pub fn meta(&self) -> Meta {
+        let updated = match self.updated.load(Ordering::SeqCst) {
+            0 => None,
+            nanos => Some(*EPOCH + Duration::from_nanos(nanos - 1)),
+        };
+        Meta {
+            updated,
+            updates: self.updates.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn update_count(&self) -> u64 {
+        self.updates.load(Ordering::SeqCst)
+    }
+}
+
+// A value with its meta, Inconsistent if a set overlapped with every read of them
+type ReadWithMeta<V> = Result<(V, Meta), Inconsistent<(V, Meta)>>;
+
+impl<T: GetterSetter> WithMeta<T> {
+    // The value together with the meta of the set that wrote it.
+    // Retries while the variable is being set, so the two always belong together. Like read_consistent() it gives up
+    // after MAX_READ_ATTEMPTS reads and returns the last, possibly mismatched, read as Inconsistent.
+    pub fn get_with_meta(&self) -> ReadWithMeta<T::InnerType> {
+        let mut attempts = 0;
+        loop {
+            let idle = self.writers.load(Ordering::SeqCst) == 0;
+            attempts += 1;
+            // The last attempt reads even during a set to have a value to return
+            if idle || attempts == MAX_READ_ATTEMPTS {
+                let updates = self.updates.load(Ordering::SeqCst);
+                let value = self.value.get();
+                let meta = self.meta();
+                if idle && self.writers.load(Ordering::SeqCst) == 0 && meta.updates == updates {
+                    return Ok((value, meta));
+                }
+                if attempts == MAX_READ_ATTEMPTS {
+                    return Err(Inconsistent((value, meta)));
+                }
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+impl<T: GetterSetter> GetterSetter for WithMeta<T> {
+    type InnerType = T::InnerType;
+
+    fn set(&self, val: Self::InnerType) {
+        self.writers.fetch_add(1, Ordering::SeqCst);
+        self.value.set(val);
+        let nanos = EPOCH.elapsed().as_nanos() as u64 + 1;
+        self.updated.fetch_max(nanos, Ordering::SeqCst);
+        self.updates.fetch_add(1, Ordering::SeqCst);
+        self.writers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn get(&self) -> Self::InnerType {
+        self.value.get()
+    }
+}
+
+impl<T: ArrayGetter> ArrayGetter for WithMeta<T> {
+    type InnerType = T::InnerType;
+
+    fn get_array(&self) -> Vec<Self::InnerType> {
+        self.value.get_array()
+    }
+}
+
+// Lets WithMeta::new() create ring buffers, which are filled with default values like the ones derive(Init) creates
+impl<T: Default + Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        RingBuffer::new([T::default(); N])
+    }
+}
@@ -0,0 +1,46 @@
+// A value which is read without locking and replaced as a whole (read-copy-update), used for the tables set! looks
+// up on every write. Modifying copies the current value, changes the copy and swaps it in.
+// The value is kept in an ArcSwap, whose readers don't share a counter, and a replaced value is freed as soon as the
+// last reader still using it is done, no matter whether other readers came along in the meantime. Nothing is locked
+// while a reader runs, so readers may modify the value themselves.
+
+use arc_swap::ArcSwapOption;
+use std::sync::{Arc, Mutex};
+
+pub(crate) struct Rcu<T> {
+    // None until the first modification
+    current: ArcSwapOption<T>,
+    // Serializes modifications
+    modifying: Mutex<()>,
+}
+
+impl<T> Rcu<T> {
+    pub(crate) const fn new() -> Rcu<T> {
+        Rcu {
+            current: ArcSwapOption::const_empty(),
+            modifying: Mutex::new(()),
+        }
+    }
+
+    // None until the value was modified for the first time
+    pub(crate) fn read<R>(&self, read: impl FnOnce(Option<&T>) -> R) -> R {
+        let current = self.current.load();
+        read(current.as_deref())
+    }
+}
+
+impl<T: Clone + Default> Rcu<T> {
+    pub(crate) fn modify<R>(&self, modify: impl FnOnce(&mut T) -> R) -> R {
+        self.try_modify(|value| Some(modify(value))).unwrap()
+    }
+
+    // Like modify, but keeps the current value if modify returns None, e.g. because there was nothing to change
+    pub(crate) fn try_modify<R>(&self, modify: impl FnOnce(&mut T) -> Option<R>) -> Option<R> {
+        let _modifying = self.modifying.lock().unwrap();
+        // Only modifications replace the value, and they hold the lock
+        let mut value = self.current.load().as_deref().cloned().unwrap_or_default();
+        let res = modify(&mut value)?;
+        self.current.store(Some(Arc::new(value)));
+        Some(res)
+    }
+}
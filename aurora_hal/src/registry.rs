@@ -0,0 +1,229 @@
+// Runtime access to the variables of an IoTree by their dotted path, e.g. "process.Sensor2.x_acc".
+// The paths are the ones used in the Callbacks.toml: "process" or "control", followed by the table and variable names of
+// the IoTree.toml. add_fields implements the PathRegistry trait for the IoTree struct, so ground commands and loggers can
+// work with any I/O tree.
+// Ring buffers are accessed like through GetterSetter: reading returns the newest value, writing appends a value.
+
+use crate::LeafId;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    U64,
+    U32,
+    U16,
+    I64,
+    I32,
+    I16,
+    F64,
+    F32,
+    Bool,
+    Str,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    U64(u64),
+    U32(u32),
+    U16(u16),
+    I64(i64),
+    I32(i32),
+    I16(i16),
+    F64(f64),
+    F32(f32),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathInfo {
+    pub path: &'static str,
+    pub value_type: ValueType,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathError {
+    UnknownPath(String),
+    TypeMismatch {
+        path: String,
+        expected: ValueType,
+        found: ValueType,
+    },
+    Parse {
+        path: String,
+        value_type: ValueType,
+        input: String,
+    },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::UnknownPath(path) => write!(f, "unknown IoTree path '{path}'"),
+            PathError::TypeMismatch {
+                path,
+                expected,
+                found,
+            } => write!(f, "'{path}' holds a {expected}, got a {found}"),
+            PathError::Parse {
+                path,
+                value_type,
+                input,
+            } => write!(f, "can't parse '{input}' as a {value_type} for '{path}'"),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl ValueType {
+    // Maps the type names used in the IoTree.toml
+    pub fn from_toml_name(name: &str) -> Option<ValueType> {
+        Some(match name {
+            "u64" => ValueType::U64,
+            "u32" => ValueType::U32,
+            "u16" => ValueType::U16,
+            "i64" => ValueType::I64,
+            "i32" => ValueType::I32,
+            "i16" => ValueType::I16,
+            "f64" => ValueType::F64,
+            "f32" => ValueType::F32,
+            "bool" => ValueType::Bool,
+            "str" => ValueType::Str,
+            _ => return None,
+        })
+    }
+
+    pub fn toml_name(self) -> &'static str {
+        match self {
+            ValueType::U64 => "u64",
+            ValueType::U32 => "u32",
+            ValueType::U16 => "u16",
+            ValueType::I64 => "i64",
+            ValueType::I32 => "i32",
+            ValueType::I16 => "i16",
+            ValueType::F64 => "f64",
+            ValueType::F32 => "f32",
+            ValueType::Bool => "bool",
+            ValueType::Str => "str",
+        }
+    }
+
+    // Returns None if the input isn't a valid value of this type
+    pub fn parse(self, input: &str) -> Option<Value> {
+        let input = input.trim();
+        match self {
+            ValueType::U64 => input.parse().ok().map(Value::U64),
+            ValueType::U32 => input.parse().ok().map(Value::U32),
+            ValueType::U16 => input.parse().ok().map(Value::U16),
+            ValueType::I64 => input.parse().ok().map(Value::I64),
+            ValueType::I32 => input.parse().ok().map(Value::I32),
+            ValueType::I16 => input.parse().ok().map(Value::I16),
+            ValueType::F64 => input.parse().ok().map(Value::F64),
+            ValueType::F32 => input.parse().ok().map(Value::F32),
+            ValueType::Bool => input.parse().ok().map(Value::Bool),
+            ValueType::Str => Some(Value::Str(input.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.toml_name())
+    }
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::U64(_) => ValueType::U64,
+            Value::U32(_) => ValueType::U32,
+            Value::U16(_) => ValueType::U16,
+            Value::I64(_) => ValueType::I64,
+            Value::I32(_) => ValueType::I32,
+            Value::I16(_) => ValueType::I16,
+            Value::F64(_) => ValueType::F64,
+            Value::F32(_) => ValueType::F32,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Str(_) => ValueType::Str,
+        }
+    }
+}
+
+// Lets set! turn the values of any variable into a Value for subscribers
+macro_rules! impl_from_for_value {
+    ($($t:ty => $variant:ident),*) => {
+        $(impl From<$t> for Value {
+            fn from(v: $t) -> Value {
+                Value::$variant(v)
+            }
+        })*
+    };
+}
+
+impl_from_for_value!(
+    u64 => U64, u32 => U32, u16 => U16, i64 => I64, i32 => I32, i16 => I16,
+    f64 => F64, f32 => F32, bool => Bool, String => Str
+);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::U64(v) => write!(f, "{v}"),
+            Value::U32(v) => write!(f, "{v}"),
+            Value::U16(v) => write!(f, "{v}"),
+            Value::I64(v) => write!(f, "{v}"),
+            Value::I32(v) => write!(f, "{v}"),
+            Value::I16(v) => write!(f, "{v}"),
+            Value::F64(v) => write!(f, "{v}"),
+            Value::F32(v) => write!(f, "{v}"),
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::Str(v) => f.write_str(v),
+        }
+    }
+}
+
+// Implemented for the IoTree struct by add_fields.
+// Values set through the registry go through set!, so they notify subscribers and run the
+// callbacks of the variable like any other set.
+pub trait PathRegistry {
+    // All variable paths of the tree, sorted by table and variable name within process and control
+    fn paths(&self) -> &'static [PathInfo];
+
+    fn get_by_path(&self, path: &str) -> Result<Value, PathError>;
+
+    // Like get_by_path, but without looking up the path, e.g. for encoding the same variables over and over.
+    // None if the tree has no variable with that id.
+    fn get_by_id(&self, id: LeafId) -> Option<Value>;
+
+    // Fails if the value doesn't have the exact type of the variable
+    fn set_by_path(&self, path: &str, value: Value) -> Result<(), PathError>;
+
+    fn value_type(&self, path: &str) -> Result<ValueType, PathError> {
+        self.paths()
+            .iter()
+            .find(|info| info.path == path)
+            .map(|info| info.value_type)
+            .ok_or_else(|| PathError::UnknownPath(path.to_string()))
+    }
+
+    // The identifier of a variable is its index in paths(), see Leaf
+    fn leaf_id(&self, path: &str) -> Result<LeafId, PathError> {
+        self.paths()
+            .iter()
+            .position(|info| info.path == path)
+            .map(|index| LeafId(index as u32))
+            .ok_or_else(|| PathError::UnknownPath(path.to_string()))
+    }
+
+    // Parses the input according to the type of the variable, e.g. for ground commands
+    fn set_by_path_str(&self, path: &str, input: &str) -> Result<(), PathError> {
+        let value_type = self.value_type(path)?;
+        let value = value_type.parse(input).ok_or_else(|| PathError::Parse {
+            path: path.to_string(),
+            value_type,
+            input: input.to_string(),
+        })?;
+        self.set_by_path(path, value)
+    }
+}
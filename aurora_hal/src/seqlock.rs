@@ -0,0 +1,83 @@
+// Lets readers of several IoTree values detect writes that happened while they were reading.
+// Writes that belong together are done in a single update() so they are only ever seen together. read_consistent()
+// retries the read until no update happened in between, which is how IoTree::snapshot() gets a consistent copy of the
+// whole tree.
+// Plain sets outside of update() are not announced here, so they don't pay for the shared counters. Every single value
+// is read atomically anyway, only the values of one update() need to be seen together.
+// Unlike a classic sequence lock any number of threads may update at the same time, so the number of ongoing updates
+// is counted next to the version, which is bumped at the end of every update.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+// Number of reads read_consistent() tries before it gives up
+pub const MAX_READ_ATTEMPTS: usize = 64;
+
+static WRITERS: AtomicUsize = AtomicUsize::new(0);
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+// Marks an ongoing update until it is dropped
+pub struct UpdateGuard {
+    _private: (),
+}
+
+pub fn begin_update() -> UpdateGuard {
+    WRITERS.fetch_add(1, Ordering::SeqCst);
+    UpdateGuard { _private: () }
+}
+
+impl Drop for UpdateGuard {
+    fn drop(&mut self) {
+        VERSION.fetch_add(1, Ordering::SeqCst);
+        WRITERS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Writes several values as one update, e.g. all axes of a sensor reading
+pub fn update<R>(write: impl FnOnce() -> R) -> R {
+    let _guard = begin_update();
+    write()
+}
+
+// Returned by read_consistent() if every attempt overlapped with an update, holds the result of the last attempt
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inconsistent<R>(pub R);
+
+impl<R> fmt::Display for Inconsistent<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "values were updated during all {MAX_READ_ATTEMPTS} reads"
+        )
+    }
+}
+
+impl<R: fmt::Debug> std::error::Error for Inconsistent<R> {}
+
+// Calls read until no update happened while it ran and returns its result.
+// Gives up after MAX_READ_ATTEMPTS reads, so readers like telemetry and logging are not starved by a busy writer, and
+// returns the last result as best effort.
+// Must not be called from inside update(), every read would overlap with its own update.
+pub fn read_consistent<R>(mut read: impl FnMut() -> R) -> Result<R, Inconsistent<R>> {
+    let mut attempts = 0;
+    loop {
+        let version = VERSION.load(Ordering::SeqCst);
+        let idle = WRITERS.load(Ordering::SeqCst) == 0;
+        attempts += 1;
+        // The last attempt reads even during an update to have a value to return
+        if idle || attempts == MAX_READ_ATTEMPTS {
+            let value = read();
+            if idle
+                && WRITERS.load(Ordering::SeqCst) == 0
+                && VERSION.load(Ordering::SeqCst) == version
+            {
+                return Ok(value);
+            }
+            if attempts == MAX_READ_ATTEMPTS {
+                return Err(Inconsistent(value));
+            }
+        }
+        thread::yield_now();
+    }
+}
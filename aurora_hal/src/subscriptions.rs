@@ -0,0 +1,233 @@
+// Notifications about value changes of IoTree variables, e.g. for loggers, telemetry or bridges to the state machine.
+// A subscription covers a variable ("process.Sensor2.x_acc") or all variables of a table ("process.Sensor2") and is
+// notified by set!, and by PathRegistry::set_by_path() and set_by_path_str() which use it, with the path, the value before
+// and after the write and the time of the write. Only values written by restore() aren't notified, like they don't run
+// callbacks.
+//
+// Changes are delivered to a closure or an mpsc channel, either immediately on the thread calling set! or coalesced:
+// coalesced changes are collected and delivered by a thread of the subscription, so a slow subscriber never holds up
+// set!. Changes of the same variable which pile up in the meantime are merged into one, with the value before the first
+// and after the last of them.
+//
+// Subscriptions end when the returned Subscription is dropped or cancelled.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::rcu::Rcu;
+use crate::{LeafId, PathError, PathRegistry, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub path: &'static str,
+    pub old: Value,
+    pub new: Value,
+    pub timestamp: Instant,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    // On the thread calling set!, every change on its own
+    Immediate,
+    // On a thread of the subscription, changes which pile up are merged per variable
+    Coalesced,
+}
+
+type OnChange = Box<dyn Fn(&Change) + Send + Sync>;
+
+#[derive(Default)]
+struct Pending {
+    changes: Vec<Change>,
+    cancelled: bool,
+}
+
+struct Queue {
+    pending: Mutex<Pending>,
+    ready: Condvar,
+}
+
+enum Sink {
+    Immediate(OnChange),
+    Coalesced(Arc<Queue>),
+}
+
+struct Subscriber {
+    id: u64,
+    sink: Sink,
+}
+
+// Subscribers of every variable indexed by its LeafId, together with the path of the variable
+type Table = Vec<Vec<(Arc<Subscriber>, &'static str)>>;
+
+static SUBSCRIBERS: Rcu<Table> = Rcu::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+pub struct Subscription {
+    id: u64,
+    queue: Option<Arc<Queue>>,
+    dispatcher: Option<JoinHandle<()>>,
+}
+
+// Calls on_change for every change of the variable or table at path
+pub fn subscribe(
+    tree: &impl PathRegistry,
+    path: &str,
+    delivery: Delivery,
+    on_change: impl Fn(&Change) + Send + Sync + 'static,
+) -> Result<Subscription, PathError> {
+    let leaves: Vec<(LeafId, &'static str)> = tree
+        .paths()
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| {
+            info.path == path
+                || (info.path.starts_with(path) && info.path[path.len()..].starts_with('.'))
+        })
+        .map(|(index, info)| (LeafId(index as u32), info.path))
+        .collect();
+    if leaves.is_empty() {
+        return Err(PathError::UnknownPath(path.to_string()));
+    }
+
+    let on_change: OnChange = Box::new(on_change);
+    let (sink, queue, dispatcher) = match delivery {
+        Delivery::Immediate => (Sink::Immediate(on_change), None, None),
+        Delivery::Coalesced => {
+            let queue = Arc::new(Queue {
+                pending: Mutex::new(Pending::default()),
+                ready: Condvar::new(),
+            });
+            let dispatcher = {
+                let queue = queue.clone();
+                thread::spawn(move || dispatch(&queue, on_change))
+            };
+            (
+                Sink::Coalesced(queue.clone()),
+                Some(queue),
+                Some(dispatcher),
+            )
+        }
+    };
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let subscriber = Arc::new(Subscriber { id, sink });
+    SUBSCRIBERS.modify(|table| {
+        for (leaf, path) in &leaves {
+            if table.len() <= leaf.index() {
+                table.resize_with(leaf.index() + 1, Vec::new);
+            }
+            table[leaf.index()].push((subscriber.clone(), *path));
+        }
+    });
+    Ok(Subscription {
+        id,
+        queue,
+        dispatcher,
+    })
+}
+
+// Sends every change of the variable or table at path to the returned receiver.
+// The subscription isn't cancelled when the receiver is dropped.
+pub fn subscribe_channel(
+    tree: &impl PathRegistry,
+    path: &str,
+    delivery: Delivery,
+) -> Result<(Subscription, Receiver<Change>), PathError> {
+    let (sender, receiver) = mpsc::channel();
+    let subscription = subscribe(tree, path, delivery, move |change| {
+        let _ = sender.send(change.clone());
+    })?;
+    Ok((subscription, receiver))
+}
+
+// Used by set! to read the old value only if someone is interested
+pub fn is_subscribed(leaf: LeafId) -> bool {
+    SUBSCRIBERS.read(|table| {
+        table
+            .and_then(|table| table.get(leaf.index()))
+            .is_some_and(|subscribers| !subscribers.is_empty())
+    })
+}
+
+// Notifies the subscribers of the variable about a write, used by set!
+pub fn notify(leaf: LeafId, old: Value, new: Value) {
+    let timestamp = Instant::now();
+    SUBSCRIBERS.read(|table| {
+        let Some(subscribers) = table.and_then(|table| table.get(leaf.index())) else {
+            return;
+        };
+        for (subscriber, path) in subscribers {
+            let change = Change {
+                path,
+                old: old.clone(),
+                new: new.clone(),
+                timestamp,
+            };
+            match &subscriber.sink {
+                Sink::Immediate(on_change) => on_change(&change),
+                Sink::Coalesced(queue) => queue.push(change),
+            }
+        }
+    })
+}
+
+impl Queue {
+    fn push(&self, change: Change) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(merged) = pending.changes.iter_mut().find(|c| c.path == change.path) {
+            merged.new = change.new;
+            merged.timestamp = change.timestamp;
+        } else {
+            pending.changes.push(change);
+        }
+        self.ready.notify_one();
+    }
+}
+
+fn dispatch(queue: &Queue, on_change: OnChange) {
+    loop {
+        let changes = {
+            let mut pending = queue.pending.lock().unwrap();
+            while pending.changes.is_empty() && !pending.cancelled {
+                pending = queue.ready.wait(pending).unwrap();
+            }
+            if pending.cancelled {
+                return;
+            }
+            std::mem::take(&mut pending.changes)
+        };
+        for change in &changes {
+            on_change(change);
+        }
+    }
+}
+
+impl Subscription {
+    // Same as dropping the subscription.
+    // Immediate deliveries running on other threads at the same time may still finish afterwards.
+    pub fn cancel(self) {}
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let id = self.id;
+        SUBSCRIBERS.modify(|table| {
+            for subscribers in table.iter_mut() {
+                subscribers.retain(|(subscriber, _)| subscriber.id != id);
+            }
+        });
+        if let Some(queue) = &self.queue {
+            queue.pending.lock().unwrap().cancelled = true;
+            queue.ready.notify_one();
+        }
+        if let Some(dispatcher) = self.dispatcher.take() {
+            // A subscription dropped by its own closure can't wait for itself
+            if dispatcher.thread().id() != thread::current().id() {
+                let _ = dispatcher.join();
+            }
+        }
+    }
+}
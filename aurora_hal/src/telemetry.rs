@@ -0,0 +1,462 @@
+// Compact binary encoding of IoTree values for the downlink.
+//
+// A frame starts with a header of the format version (1 byte) and the hash of the schema (4 bytes, little endian), followed
+// by the values of all fields of the schema in order, without any tags:
+// - numbers are little endian with the size of their type, bool is a single 0 or 1 byte
+// - strings are their length as u16 followed by the UTF-8 bytes, longer strings are truncated
+// Ring buffers are sent with their newest value, like PathRegistry::get_by_path returns them.
+//
+// Channels defined in the [Telemetry] table of the IoTree.toml send subsets of the tree at their own rate, each with its own
+// schema. add_fields checks at compile time that the channels fit into the link budget.
+//
+// The flight computer builds the schema from the I/O tree generated by add_fields, the ground station from the same
+// IoTree.toml. Both list the variables in the same order, so the schemas and their hashes match, and a frame of a
+// different I/O tree definition is rejected instead of being decoded into garbage.
+
+use crate::{
+    read_consistent, Inconsistent, LeafId, PathError, PathInfo, PathRegistry, Value, ValueType,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub const FORMAT_VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaField {
+    pub path: String,
+    pub value_type: ValueType,
+}
+
+#[derive(Clone, Debug)]
+pub struct Schema {
+    fields: Vec<SchemaField>,
+    hash: u32,
+    // Where the fields are expected in the I/O tree, see leaf_ids()
+    ids: Vec<LeafId>,
+}
+
+// Schemas with the same fields are the same, no matter where the fields were expected in the tree
+impl PartialEq for Schema {
+    fn eq(&self, other: &Self) -> bool {
+        self.fields == other.fields
+    }
+}
+
+impl Eq for Schema {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TelemetryError {
+    Toml(String),
+    Path(PathError),
+    Version { expected: u8, found: u8 },
+    SchemaMismatch { expected: u32, found: u32 },
+    // The frame ended in the middle of a value or the header
+    Truncated,
+    TrailingBytes(usize),
+    InvalidValue { path: String },
+    ValueCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryError::Toml(e) => write!(f, "failed to parse IoTree definition: {e}"),
+            TelemetryError::Path(e) => write!(f, "{e}"),
+            TelemetryError::Version { expected, found } => {
+                write!(f, "frame has format version {found}, expected {expected}")
+            }
+            TelemetryError::SchemaMismatch { expected, found } => {
+                write!(
+                    f,
+                    "frame has schema hash {found:08x}, expected {expected:08x}"
+                )
+            }
+            TelemetryError::Truncated => write!(f, "frame is truncated"),
+            TelemetryError::TrailingBytes(n) => write!(f, "frame has {n} trailing bytes"),
+            TelemetryError::InvalidValue { path } => write!(f, "invalid value for '{path}'"),
+            TelemetryError::ValueCount { expected, found } => {
+                write!(f, "got {found} values for a schema of {expected} fields")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TelemetryError {}
+
+impl From<PathError> for TelemetryError {
+    fn from(e: PathError) -> Self {
+        TelemetryError::Path(e)
+    }
+}
+
+// 32 bit FNV-1a, stable across platforms and compiler versions
+fn fnv1a(hash: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+impl Schema {
+    pub fn new(fields: Vec<SchemaField>) -> Schema {
+        let mut hash = 0x811c_9dc5;
+        for field in &fields {
+            hash = fnv1a(hash, field.path.as_bytes());
+            hash = fnv1a(hash, b":");
+            hash = fnv1a(hash, field.value_type.toml_name().as_bytes());
+            hash = fnv1a(hash, b";");
+        }
+        // All variables of a tree in order, which is how of() and from_io_tree_toml() list them
+        let ids = (0..fields.len() as u32).map(LeafId).collect();
+        Schema { fields, hash, ids }
+    }
+
+    // All variables of an I/O tree, e.g. Schema::of(&*IOTREE)
+    pub fn of(tree: &impl PathRegistry) -> Schema {
+        Self::from_paths(tree.paths())
+    }
+
+    pub fn from_paths(paths: &[PathInfo]) -> Schema {
+        Schema::new(
+            paths
+                .iter()
+                .map(|info| SchemaField {
+                    path: info.path.to_string(),
+                    value_type: info.value_type,
+                })
+                .collect(),
+        )
+    }
+
+    // All variables of an IoTree.toml, in the same order as the I/O tree generated from it
+    pub fn from_io_tree_toml(toml: &str) -> Result<Schema, TelemetryError> {
+        let toml = toml
+            .parse::<toml::Value>()
+            .map_err(|e| TelemetryError::Toml(e.to_string()))?;
+        let mut fields = Vec::new();
+        for (table, path) in [("Process", "process"), ("Control", "control")] {
+            if let Some(value) = toml.get(table) {
+                collect_fields(path, value, &mut fields)?;
+            }
+        }
+        Ok(Schema::new(fields))
+    }
+
+    // A schema of the given variables in the given order, e.g. for a low rate channel
+    pub fn subset(&self, paths: &[&str]) -> Result<Schema, TelemetryError> {
+        let (fields, ids) = paths
+            .iter()
+            .map(|&path| {
+                self.fields
+                    .iter()
+                    .zip(&self.ids)
+                    .find(|(field, _)| field.path == path)
+                    .map(|(field, &id)| (field.clone(), id))
+                    .ok_or_else(|| PathError::UnknownPath(path.to_string()).into())
+            })
+            .collect::<Result<_, TelemetryError>>()?;
+        Ok(Schema {
+            ids,
+            ..Schema::new(fields)
+        })
+    }
+
+    pub fn fields(&self) -> &[SchemaField] {
+        &self.fields
+    }
+
+    pub fn hash(&self) -> u32 {
+        self.hash
+    }
+
+    // Reads all fields of the schema from the tree as one consistent snapshot and encodes them into a frame.
+    // If updates keep overlapping with the read, the values of the last attempt are sent rather than no frame at all.
+    pub fn encode(&self, tree: &impl PathRegistry) -> Result<Vec<u8>, TelemetryError> {
+        let ids = self.leaf_ids(tree)?;
+        let values = read_consistent(|| {
+            self.fields
+                .iter()
+                .zip(ids.iter())
+                .map(|(field, &id)| {
+                    tree.get_by_id(id)
+                        .ok_or_else(|| PathError::UnknownPath(field.path.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or_else(|Inconsistent(values)| values)?;
+        self.encode_values(&values)
+    }
+
+    // The ids of the fields in the tree. They are known from building the schema unless its fields were listed by hand
+    // in a different order than the tree has them, only then the paths are looked up.
+    fn leaf_ids(&self, tree: &impl PathRegistry) -> Result<Cow<'_, [LeafId]>, PathError> {
+        let paths = tree.paths();
+        let known = self.fields.iter().zip(&self.ids).all(|(field, id)| {
+            paths
+                .get(id.index())
+                .is_some_and(|info| info.path == field.path)
+        });
+        if known {
+            return Ok(Cow::Borrowed(&self.ids));
+        }
+        self.fields
+            .iter()
+            .map(|field| tree.leaf_id(&field.path))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Cow::Owned)
+    }
+
+    // Encodes values given in the order of the fields of the schema
+    pub fn encode_values(&self, values: &[Value]) -> Result<Vec<u8>, TelemetryError> {
+        if values.len() != self.fields.len() {
+            return Err(TelemetryError::ValueCount {
+                expected: self.fields.len(),
+                found: values.len(),
+            });
+        }
+        let mut frame = Vec::with_capacity(HEADER_LEN + 8 * values.len());
+        frame.push(FORMAT_VERSION);
+        frame.extend_from_slice(&self.hash.to_le_bytes());
+        for (field, value) in self.fields.iter().zip(values) {
+            if value.value_type() != field.value_type {
+                return Err(PathError::TypeMismatch {
+                    path: field.path.clone(),
+                    expected: field.value_type,
+                    found: value.value_type(),
+                }
+                .into());
+            }
+            encode_value(value, &mut frame);
+        }
+        Ok(frame)
+    }
+
+    // Returns the values of the frame together with their paths, in the order of the fields of the schema
+    pub fn decode(&self, frame: &[u8]) -> Result<Vec<(&str, Value)>, TelemetryError> {
+        let mut reader = Reader { frame, pos: 0 };
+        let version = reader.take::<1>()?[0];
+        if version != FORMAT_VERSION {
+            return Err(TelemetryError::Version {
+                expected: FORMAT_VERSION,
+                found: version,
+            });
+        }
+        let hash = u32::from_le_bytes(reader.take()?);
+        if hash != self.hash {
+            return Err(TelemetryError::SchemaMismatch {
+                expected: self.hash,
+                found: hash,
+            });
+        }
+
+        let mut values = Vec::with_capacity(self.fields.len());
+        for field in &self.fields {
+            let invalid = || TelemetryError::InvalidValue {
+                path: field.path.clone(),
+            };
+            let value = match field.value_type {
+                ValueType::U64 => Value::U64(u64::from_le_bytes(reader.take()?)),
+                ValueType::U32 => Value::U32(u32::from_le_bytes(reader.take()?)),
+                ValueType::U16 => Value::U16(u16::from_le_bytes(reader.take()?)),
+                ValueType::I64 => Value::I64(i64::from_le_bytes(reader.take()?)),
+                ValueType::I32 => Value::I32(i32::from_le_bytes(reader.take()?)),
+                ValueType::I16 => Value::I16(i16::from_le_bytes(reader.take()?)),
+                ValueType::F64 => Value::F64(f64::from_le_bytes(reader.take()?)),
+                ValueType::F32 => Value::F32(f32::from_le_bytes(reader.take()?)),
+                ValueType::Bool => match reader.take::<1>()?[0] {
+                    0 => Value::Bool(false),
+                    1 => Value::Bool(true),
+                    _ => return Err(invalid()),
+                },
+                ValueType::Str => {
+                    let len = u16::from_le_bytes(reader.take()?) as usize;
+                    let bytes = reader.take_slice(len)?;
+                    Value::Str(String::from_utf8(bytes.to_vec()).map_err(|_| invalid())?)
+                }
+            };
+            values.push((field.path.as_str(), value));
+        }
+
+        match frame.len() - reader.pos {
+            0 => Ok(values),
+            n => Err(TelemetryError::TrailingBytes(n)),
+        }
+    }
+}
+
+// A downlink channel of the [Telemetry] table of the IoTree.toml, add_fields generates them as IoTree::TELEMETRY_CHANNELS
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelInfo {
+    pub name: &'static str,
+    // Frames per second
+    pub rate: f64,
+    // Frames due at the same time are sent by ascending priority
+    pub priority: u8,
+    pub fields: &'static [&'static str],
+    pub frame_len: usize,
+}
+
+impl ChannelInfo {
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.rate)
+    }
+
+    pub fn schema(&self, tree: &impl PathRegistry) -> Result<Schema, TelemetryError> {
+        Schema::of(tree).subset(self.fields)
+    }
+}
+
+// The schemas of all channels of an IoTree.toml, for the ground station
+pub fn channel_schemas_from_toml(toml: &str) -> Result<Vec<(String, Schema)>, TelemetryError> {
+    let tree = Schema::from_io_tree_toml(toml)?;
+    let toml = toml
+        .parse::<toml::Value>()
+        .map_err(|e| TelemetryError::Toml(e.to_string()))?;
+    let channels = match toml.get("Telemetry") {
+        Some(toml::Value::Table(channels)) => channels,
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut schemas = Vec::new();
+    for (name, channel) in channels {
+        let fields = match channel.get("fields") {
+            Some(toml::Value::Array(fields)) => fields,
+            _ => continue,
+        };
+        let paths = fields
+            .iter()
+            .map(|field| {
+                field.as_str().ok_or_else(|| {
+                    TelemetryError::Toml(format!("fields of channel '{name}' must be paths"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        schemas.push((name.clone(), tree.subset(&paths)?));
+    }
+    Ok(schemas)
+}
+
+// Decides which channels are due and encodes their frames
+pub struct ChannelScheduler {
+    channels: Vec<(ChannelInfo, Schema, Instant)>,
+}
+
+impl ChannelScheduler {
+    // All channels are first due at start
+    pub fn new(
+        tree: &impl PathRegistry,
+        channels: &[ChannelInfo],
+        start: Instant,
+    ) -> Result<ChannelScheduler, TelemetryError> {
+        let mut channels = channels
+            .iter()
+            .map(|channel| Ok((*channel, channel.schema(tree)?, start)))
+            .collect::<Result<Vec<_>, TelemetryError>>()?;
+        channels.sort_by_key(|(channel, _, _)| channel.priority);
+        Ok(ChannelScheduler { channels })
+    }
+
+    // The next time a channel is due
+    pub fn next_due(&self) -> Option<Instant> {
+        self.channels.iter().map(|(_, _, due)| *due).min()
+    }
+
+    // Encodes a frame for every channel due at now, most important first.
+    // A channel that fell behind by more than a period skips the missed frames instead of sending them in a burst.
+    pub fn poll(
+        &mut self,
+        tree: &impl PathRegistry,
+        now: Instant,
+    ) -> Result<Vec<(&'static str, Vec<u8>)>, TelemetryError> {
+        let mut frames = Vec::new();
+        for (channel, schema, due) in &mut self.channels {
+            if *due > now {
+                continue;
+            }
+            frames.push((channel.name, schema.encode(tree)?));
+            *due += channel.period();
+            if *due <= now {
+                *due = now + channel.period();
+            }
+        }
+        Ok(frames)
+    }
+}
+
+fn collect_fields(
+    path: &str,
+    value: &toml::Value,
+    fields: &mut Vec<SchemaField>,
+) -> Result<(), TelemetryError> {
+    let type_name = match value {
+        toml::Value::String(type_name) => Some(type_name),
+        toml::Value::Table(table) => match table.get("type") {
+            Some(toml::Value::String(type_name)) => Some(type_name),
+            _ => None,
+        },
+        _ => return Ok(()),
+    };
+
+    match (type_name, value) {
+        (Some(type_name), _) => {
+            let value_type = ValueType::from_toml_name(type_name).ok_or_else(|| {
+                TelemetryError::Toml(format!("unknown type '{type_name}' of '{path}'"))
+            })?;
+            fields.push(SchemaField {
+                path: path.to_string(),
+                value_type,
+            });
+        }
+        (None, toml::Value::Table(table)) => {
+            for (key, value) in table {
+                collect_fields(&format!("{path}.{key}"), value, fields)?;
+            }
+        }
+        (None, _) => {}
+    }
+    Ok(())
+}
+
+fn encode_value(value: &Value, frame: &mut Vec<u8>) {
+    match value {
+        Value::U64(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::U32(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::U16(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::I64(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::I32(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::I16(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::F64(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::F32(v) => frame.extend_from_slice(&v.to_le_bytes()),
+        Value::Bool(v) => frame.push(u8::from(*v)),
+        Value::Str(v) => {
+            let mut len = v.len().min(u16::MAX as usize);
+            // Don't cut a character in half
+            while !v.is_char_boundary(len) {
+                len -= 1;
+            }
+            frame.extend_from_slice(&(len as u16).to_le_bytes());
+            frame.extend_from_slice(&v.as_bytes()[..len]);
+        }
+    }
+}
+
+struct Reader<'a> {
+    frame: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], TelemetryError> {
+        let bytes = self
+            .frame
+            .get(self.pos..self.pos + len)
+            .ok_or(TelemetryError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], TelemetryError> {
+        Ok(self.take_slice(N)?.try_into().unwrap())
+    }
+}
@@ -0,0 +1,138 @@
+use aurora_hal::{
+    register_callback, set, unregister_callback, CallbackId, CallbackRegistry, GetterSetter, Leaf,
+    LeafId, MAX_CALLBACK_DEPTH,
+};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[test]
+fn callbacks_can_call_set() {
+    static A: Leaf<AtomicU32, 0> = Leaf::with_value(AtomicU32::new(0));
+    static B: Leaf<AtomicU32, 1> = Leaf::with_value(AtomicU32::new(0));
+    static C: Leaf<AtomicU32, 2> = Leaf::with_value(AtomicU32::new(0));
+
+    register_callback(A.id(), || true, || set!(B, A.get() * 2));
+    register_callback(B.id(), || true, || set!(C, B.get() + 1));
+
+    set!(A, 5);
+    assert_eq!(B.get(), 10);
+    assert_eq!(C.get(), 11);
+}
+
+#[test]
+fn nesting_is_bounded() {
+    let registry = Arc::new(CallbackRegistry::new());
+    let runs = Arc::new(AtomicU32::new(0));
+    {
+        let registry2 = registry.clone();
+        let runs = runs.clone();
+        // Every run triggers itself again
+        registry.register(
+            LeafId(0),
+            0,
+            || true,
+            move || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                registry2.run(LeafId(0));
+            },
+        );
+    }
+
+    registry.run(LeafId(0));
+    assert_eq!(runs.load(Ordering::SeqCst), MAX_CALLBACK_DEPTH as u32);
+    assert_eq!(registry.skipped_runs(), 1);
+
+    // The depth is back to zero afterwards
+    registry.run(LeafId(0));
+    assert_eq!(runs.load(Ordering::SeqCst), 2 * MAX_CALLBACK_DEPTH as u32);
+}
+
+#[test]
+fn callbacks_can_unregister_themselves() {
+    static D: Leaf<AtomicU32, 3> = Leaf::with_value(AtomicU32::new(0));
+    static RUNS: AtomicU32 = AtomicU32::new(0);
+    static ID: Mutex<Option<CallbackId>> = Mutex::new(None);
+
+    let id = register_callback(
+        D.id(),
+        || D.get() > 2,
+        || {
+            RUNS.fetch_add(1, Ordering::SeqCst);
+            assert!(unregister_callback(ID.lock().unwrap().unwrap()));
+        },
+    );
+    *ID.lock().unwrap() = Some(id);
+
+    for v in 0..6 {
+        set!(D, v);
+    }
+    assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn registering_while_dispatching() {
+    let registry = Arc::new(CallbackRegistry::new());
+    let calls = Arc::new(AtomicU64::new(0));
+    {
+        let calls = calls.clone();
+        registry.register(
+            LeafId(0),
+            0,
+            || true,
+            move || {
+                calls.fetch_add(1, Ordering::Relaxed);
+            },
+        );
+    }
+
+    let dispatchers: Vec<_> = (0..3)
+        .map(|_| {
+            let registry = registry.clone();
+            thread::spawn(move || {
+                for _ in 0..20_000 {
+                    registry.run(LeafId(0));
+                }
+            })
+        })
+        .collect();
+
+    // Replaces the table over and over while the dispatchers read it
+    for i in 0..500 {
+        let id = registry.register(LeafId(1 + i % 10), 0, || false, || {});
+        assert!(registry.unregister(id));
+    }
+
+    for dispatcher in dispatchers {
+        dispatcher.join().unwrap();
+    }
+    assert_eq!(calls.load(Ordering::Relaxed), 60_000);
+    assert_eq!(registry.callbacks(LeafId(0)).len(), 1);
+    assert_eq!(registry.skipped_runs(), 0);
+}
+
+#[test]
+fn replaced_tables_are_freed_while_dispatching() {
+    let registry = Arc::new(CallbackRegistry::new());
+    registry.register(LeafId(2), 0, || true, || {});
+    let entry = registry.callbacks(LeafId(2))[0].clone();
+    {
+        let registry2 = registry.clone();
+        // Replaces the table over and over while run() still reads the old one
+        registry.register(
+            LeafId(0),
+            0,
+            || true,
+            move || {
+                for _ in 0..100 {
+                    let id = registry2.register(LeafId(1), 0, || false, || {});
+                    assert!(registry2.unregister(id));
+                }
+            },
+        );
+    }
+
+    registry.run(LeafId(0));
+    // Only the current table and this test hold the entry, the replaced tables are gone
+    assert_eq!(Arc::strong_count(&entry), 2);
+}
@@ -0,0 +1,15 @@
+use aurora_hal::{init_io_tree, set, GetterSetter, PathRegistry};
+
+// The example tree of the README
+init_io_tree!("IoTree.toml", "Callbacks.toml");
+
+#[test]
+fn example_callbacks_limit_pressure() {
+    init_callbacks();
+    // Callbacks.toml: the pressure is set back to 5 once it is above 5
+    set!(IOTREE.process.m_Sensor1.pressure, 3);
+    assert_eq!(IOTREE.process.m_Sensor1.pressure.get(), 3);
+    set!(IOTREE.process.m_Sensor1.pressure, 8);
+    assert_eq!(IOTREE.process.m_Sensor1.pressure.get(), 5);
+    assert!(IOTREE.leaf_id("control.state").is_ok());
+}
@@ -0,0 +1,81 @@
+use aurora_hal::{expr, init_io_tree, set};
+
+init_io_tree!(
+    "tests/config/ExpressionIoTree.toml",
+    "tests/config/ExpressionCallbacks.toml"
+);
+
+#[test]
+fn functions() {
+    init_callbacks();
+    set!(IOTREE.process.m_Gyro.x, -100);
+    assert!(!IOTREE.control.gyro_alarm.get());
+    set!(IOTREE.process.m_Gyro.x, -400);
+    assert!(IOTREE.control.gyro_alarm.get());
+}
+
+#[test]
+fn ring_buffer_aggregates() {
+    init_callbacks();
+    for v in [1.0, 2.0, 3.0] {
+        set!(IOTREE.process.m_Imu.acc, v);
+    }
+    assert!(!IOTREE.control.accelerating.get());
+    set!(IOTREE.process.m_Imu.acc, 4.0);
+    assert!(IOTREE.control.accelerating.get());
+}
+
+#[test]
+fn empty_windows_have_no_value() {
+    let values = [1.0, 2.0];
+    assert_eq!(expr::mean(&values, Some(0.0)), None);
+    assert_eq!(expr::slope(&values, Some(0.0)), None);
+    assert_eq!(expr::mean::<f64>(&[], None), None);
+
+    init_callbacks();
+    set!(IOTREE.process.m_Imu.acc, 1.0);
+    // Neither the condition nor its negation holds, and the assignment is skipped
+    assert!(!IOTREE.control.empty_window.get());
+    assert_eq!(IOTREE.control.window_mean.get(), 0.0);
+}
+
+#[test]
+fn not_binds_like_unary_minus() {
+    init_callbacks();
+    // !control.armed > control.armed is (!armed) > armed, which holds only while not armed
+    set!(IOTREE.control.armed, true);
+    assert!(!IOTREE.control.disarmed.get());
+    set!(IOTREE.control.armed, false);
+    assert!(IOTREE.control.disarmed.get());
+}
+
+#[test]
+fn logic_unary_minus_and_division() {
+    init_callbacks();
+    set!(IOTREE.process.m_Baro.pressure, 500);
+    assert_eq!(IOTREE.control.count.get(), 1);
+    assert_eq!(IOTREE.control.target.get(), 0.0);
+
+    set!(IOTREE.process.m_Baro.pressure, 1500);
+    assert_eq!(IOTREE.control.count.get(), 2);
+    assert_eq!(IOTREE.control.target.get(), -749.0);
+
+    set!(IOTREE.process.m_Baro.pressure, 2500);
+    assert_eq!(IOTREE.control.count.get(), 3);
+    assert_eq!(IOTREE.control.target.get(), -749.0);
+}
+
+#[test]
+fn aggregate_functions() {
+    let values = [1.0, 2.0, 4.0, 8.0];
+    assert_eq!(expr::mean(&values, None), Some(3.75));
+    assert_eq!(expr::mean(&values, Some(2.0)), Some(6.0));
+    assert_eq!(expr::slope(&[1u32, 3, 5], None), Some(2.0));
+    assert_eq!(expr::slope(&values, Some(1.0)), Some(0.0));
+    assert_eq!(expr::last(&values, 1.0), Some(8.0));
+    assert_eq!(expr::last(&values, 3.0), Some(2.0));
+    assert_eq!(expr::last(&values, 10.0), Some(1.0));
+    assert_eq!(expr::last::<f64>(&[], 1.0), None);
+    assert_eq!(expr::clamp(5.0, 0.0, 1.0), 1.0);
+    assert_eq!(expr::clamp(5.0, 2.0, 1.0), 1.0);
+}
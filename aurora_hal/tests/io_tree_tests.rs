@@ -0,0 +1,157 @@
+use aurora_hal::{
+    init_io_tree, set, ArrayGetter, GetterSetter, LeafId, PathError, PathInfo, PathRegistry, Value,
+    ValueType, CALLBACKS,
+};
+
+init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+
+#[test]
+fn io_tree_from_config_file() {
+    let tree = IoTree::new();
+    assert_eq!(tree.process.m_Baro.altitude.get(), 0.0);
+    tree.process.m_Baro.altitude.set(120.5);
+    assert_eq!(tree.process.m_Baro.altitude.get(), 120.5);
+
+    GetterSetter::set(&tree.process.m_Imu.acc, 1.0);
+    assert_eq!(tree.process.m_Imu.acc.get_array(), vec![0.0, 0.0, 0.0, 1.0]);
+    assert!(!tree.control.armed.get());
+}
+
+#[test]
+fn config_callbacks_fire_on_set() {
+    init_callbacks();
+    // Callbacks.toml: arms once the altitude is above 100. No other test writes these variables of IOTREE.
+    set!(IOTREE.process.m_Baro.altitude, 50.0);
+    assert!(!IOTREE.control.armed.get());
+    // The spelling of the path doesn't matter
+    set!(IOTREE.process.m_Baro.altitude, 120.5);
+    assert!(IOTREE.control.armed.get());
+}
+
+#[test]
+fn leaf_ids_follow_paths() {
+    for (index, info) in IOTREE.paths().iter().enumerate() {
+        assert_eq!(IOTREE.leaf_id(info.path), Ok(LeafId(index as u32)));
+    }
+    assert_eq!(
+        IOTREE.leaf_id("process.Imu.acc"),
+        Ok(IOTREE.process.m_Imu.acc.id())
+    );
+    assert_eq!(IOTREE.control.armed.id(), IoTree::new().control.armed.id());
+    assert_eq!(
+        IOTREE.leaf_id("process.Imu"),
+        Err(PathError::UnknownPath("process.Imu".to_string()))
+    );
+}
+
+#[test]
+fn callbacks_from_config_file() {
+    init_callbacks();
+    // A second call doesn't register the callbacks again
+    init_callbacks();
+    let chain = CALLBACKS.callbacks(IOTREE.process.m_Baro.altitude.id());
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[0].priority, -1);
+    assert_eq!(chain[1].priority, 0);
+}
+
+#[test]
+fn lists_paths() {
+    assert_eq!(
+        IOTREE.paths(),
+        &[
+            PathInfo {
+                path: "process.Baro.altitude",
+                value_type: ValueType::F32
+            },
+            PathInfo {
+                path: "process.Gps.speed",
+                value_type: ValueType::F64
+            },
+            PathInfo {
+                path: "process.Gyro.x",
+                value_type: ValueType::I64
+            },
+            PathInfo {
+                path: "process.Gyro.y",
+                value_type: ValueType::I64
+            },
+            PathInfo {
+                path: "process.Imu.acc",
+                value_type: ValueType::F64
+            },
+            PathInfo {
+                path: "control.armed",
+                value_type: ValueType::Bool
+            },
+            PathInfo {
+                path: "control.recovery_enabled",
+                value_type: ValueType::Bool
+            },
+        ]
+    );
+}
+
+#[test]
+fn get_and_set_by_path() {
+    IOTREE
+        .set_by_path("process.Gps.speed", Value::F64(2.5))
+        .unwrap();
+    assert_eq!(IOTREE.process.m_Gps.speed.get(), 2.5);
+    assert_eq!(IOTREE.get_by_path("process.Gps.speed"), Ok(Value::F64(2.5)));
+
+    IOTREE
+        .set_by_path_str("control.recovery_enabled", "true")
+        .unwrap();
+    assert!(IOTREE.control.recovery_enabled.get());
+    assert_eq!(
+        IOTREE.get_by_path("control.recovery_enabled"),
+        Ok(Value::Bool(true))
+    );
+}
+
+#[test]
+fn get_by_id_matches_get_by_path() {
+    let tree = IoTree::new();
+    tree.process.m_Gyro.y.set(-2);
+    for info in tree.paths() {
+        let id = tree.leaf_id(info.path).unwrap();
+        assert_eq!(tree.get_by_id(id), tree.get_by_path(info.path).ok());
+    }
+    assert_eq!(tree.get_by_id(LeafId(tree.paths().len() as u32)), None);
+}
+
+#[test]
+fn rejects_invalid_paths_and_values() {
+    assert_eq!(
+        IOTREE.get_by_path("process.Baro"),
+        Err(PathError::UnknownPath("process.Baro".to_string()))
+    );
+    assert_eq!(
+        IOTREE.set_by_path("process.Imu.acc", Value::U32(1)),
+        Err(PathError::TypeMismatch {
+            path: "process.Imu.acc".to_string(),
+            expected: ValueType::F64,
+            found: ValueType::U32,
+        })
+    );
+    assert!(matches!(
+        IOTREE.set_by_path_str("control.recovery_enabled", "yes"),
+        Err(PathError::Parse { .. })
+    ));
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let tree = IoTree::new();
+    GetterSetter::set(&tree.process.m_Imu.acc, 3.0);
+    tree.process.m_Baro.altitude.set(42.0);
+    let snapshot = tree.snapshot().unwrap();
+    assert_eq!(snapshot.process.m_Baro.altitude, 42.0);
+    assert_eq!(snapshot.process.m_Imu.acc, [0.0, 0.0, 0.0, 3.0]);
+
+    tree.process.m_Baro.altitude.set(0.0);
+    GetterSetter::set(&tree.process.m_Imu.acc, 4.0);
+    tree.restore(&snapshot);
+    assert_eq!(tree.snapshot().unwrap(), snapshot);
+}
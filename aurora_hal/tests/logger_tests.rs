@@ -0,0 +1,151 @@
+use aurora_hal::logger::{logs_to_csv, spawn_periodic, LogReader, Logger, LoggerConfig};
+use aurora_hal::{init_io_tree, GetterSetter, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+
+fn config(name: &str) -> LoggerConfig {
+    let directory = std::env::temp_dir().join(format!("aurora_log_{}_{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    LoggerConfig {
+        directory,
+        ..LoggerConfig::default()
+    }
+}
+
+fn altitudes(path: &PathBuf) -> Vec<Value> {
+    let mut reader = LogReader::open(path).unwrap();
+    let mut altitudes = Vec::new();
+    while let Some(record) = reader.next_record() {
+        altitudes.push(record.values[0].clone());
+    }
+    assert!(!reader.is_torn());
+    altitudes
+}
+
+#[test]
+fn writes_and_reads_records() {
+    let config = config("records");
+    let tree = IoTree::new();
+    let mut logger = Logger::create(config.clone(), &tree).unwrap();
+    for altitude in [10.0, 20.0, 30.0] {
+        tree.process.m_Baro.altitude.set(altitude);
+        logger.record(&tree).unwrap();
+    }
+    drop(logger);
+
+    let mut reader = LogReader::open(config.file_path(0)).unwrap();
+    assert_eq!(reader.schema().fields()[0].path, "process.Baro.altitude");
+    let first = reader.next_record().unwrap();
+    assert_eq!(first.values[0], Value::F32(10.0));
+    assert_eq!(first.values.len(), reader.schema().fields().len());
+    let second = reader.next_record().unwrap();
+    assert!(second.timestamp >= first.timestamp);
+    assert!(reader.next_record().is_some());
+    assert!(reader.next_record().is_none());
+    assert!(!reader.is_torn());
+
+    // A new logger doesn't overwrite the existing log
+    let logger = Logger::create(config.clone(), &tree).unwrap();
+    assert_eq!(logger.current_file(), config.file_path(1));
+    fs::remove_dir_all(config.directory).unwrap();
+}
+
+#[test]
+fn rotates_and_preallocates_files() {
+    let config = LoggerConfig {
+        max_file_size: 600,
+        preallocate: 600,
+        ..config("rotation")
+    };
+    let tree = IoTree::new();
+    let mut logger = Logger::create(config.clone(), &tree).unwrap();
+    for i in 0..10 {
+        tree.process.m_Baro.altitude.set(i as f32);
+        logger.record(&tree).unwrap();
+    }
+    drop(logger);
+
+    let files = config.files();
+    assert!(files.len() > 1);
+    assert!(files
+        .iter()
+        .all(|file| fs::metadata(file).unwrap().len() == 600));
+    let logged: Vec<Value> = files.iter().flat_map(altitudes).collect();
+    let expected: Vec<Value> = (0..10).map(|i| Value::F32(i as f32)).collect();
+    assert_eq!(logged, expected);
+    fs::remove_dir_all(config.directory).unwrap();
+}
+
+#[test]
+fn keeps_records_before_a_torn_record() {
+    let config = config("torn");
+    let tree = IoTree::new();
+    let mut logger = Logger::create(config.clone(), &tree).unwrap();
+    tree.process.m_Baro.altitude.set(1.0);
+    logger.record(&tree).unwrap();
+    logger.record(&tree).unwrap();
+    drop(logger);
+
+    // Power loss in the middle of the third record
+    let path = config.file_path(0);
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[60, 0, 0, 0, 1, 2, 3]).unwrap();
+    drop(file);
+
+    let mut reader = LogReader::open(&path).unwrap();
+    assert!(reader.next_record().is_some());
+    assert!(reader.next_record().is_some());
+    assert!(reader.next_record().is_none());
+    assert!(reader.is_torn());
+    fs::remove_dir_all(config.directory).unwrap();
+}
+
+#[test]
+fn converts_logs_to_csv() {
+    let config = LoggerConfig {
+        max_file_size: 300,
+        ..config("csv")
+    };
+    let tree = IoTree::new();
+    tree.process.m_Gyro.x.set(-3);
+    tree.control.armed.set(true);
+    let mut logger = Logger::create(config.clone(), &tree).unwrap();
+    for _ in 0..4 {
+        logger.record(&tree).unwrap();
+    }
+    drop(logger);
+
+    let csv_path = config.directory.join("flight.csv");
+    logs_to_csv(&config.files(), &csv_path).unwrap();
+    let csv = fs::read_to_string(&csv_path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "time,process.Baro.altitude,process.Gps.speed,process.Gyro.x,process.Gyro.y,\
+         process.Imu.acc,control.armed,control.recovery_enabled"
+    );
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len(), 4);
+    assert!(rows
+        .iter()
+        .all(|row| row.ends_with(",0,0,-3,0,0,true,false")));
+    fs::remove_dir_all(config.directory).unwrap();
+}
+
+#[test]
+fn logs_periodically() {
+    static TREE: std::sync::OnceLock<IoTree> = std::sync::OnceLock::new();
+    let tree = TREE.get_or_init(IoTree::new);
+    let config = config("periodic");
+    let logger = Logger::create(config.clone(), tree).unwrap();
+    let handle = spawn_periodic(logger, tree, Duration::from_millis(5));
+    std::thread::sleep(Duration::from_millis(50));
+    handle.stop().unwrap();
+
+    assert!(altitudes(&config.file_path(0)).len() >= 2);
+    fs::remove_dir_all(config.directory).unwrap();
+}
@@ -0,0 +1,123 @@
+use aurora_hal::{
+    init_io_tree, ArrayGetter, GetterSetter, Inconsistent, PathRegistry, Value, WithMeta, CALLBACKS,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+init_io_tree!(
+    "tests/config/MetaIoTree.toml",
+    "tests/config/MetaCallbacks.toml"
+);
+
+#[test]
+fn get_with_meta_tracks_sets() {
+    let tree = IoTree::new();
+    let (altitude, meta) = tree.process.m_Baro.altitude.get_with_meta().unwrap();
+    assert_eq!(altitude, 0.0);
+    assert_eq!(meta.updated, None);
+    assert_eq!(meta.updates, 0);
+    assert!(meta.is_stale(Duration::from_secs(3600)));
+
+    GetterSetter::set(&tree.process.m_Baro.altitude, 120.5);
+    GetterSetter::set(&tree.process.m_Baro.altitude, 121.0);
+    let (altitude, meta) = tree.process.m_Baro.altitude.get_with_meta().unwrap();
+    assert_eq!(altitude, 121.0);
+    assert_eq!(meta.updates, 2);
+    assert!(meta.age().unwrap() < Duration::from_secs(1));
+    assert!(!meta.is_stale(Duration::from_secs(1)));
+
+    thread::sleep(Duration::from_millis(20));
+    assert!(tree.process.m_Baro.altitude.meta().age().unwrap() >= Duration::from_millis(20));
+}
+
+// A variable whose next set blocks until the test lets it finish
+#[derive(Default)]
+struct Blocking {
+    value: AtomicU32,
+}
+
+static ENTERED: Mutex<Option<mpsc::Sender<()>>> = Mutex::new(None);
+static FINISH: Mutex<Option<mpsc::Receiver<()>>> = Mutex::new(None);
+
+impl GetterSetter for Blocking {
+    type InnerType = u32;
+
+    fn set(&self, val: u32) {
+        self.value.store(val, Ordering::SeqCst);
+        if let Some(entered) = ENTERED.lock().unwrap().take() {
+            entered.send(()).unwrap();
+            let finish = FINISH.lock().unwrap().take().unwrap();
+            let _ = finish.recv();
+        }
+    }
+
+    fn get(&self) -> u32 {
+        self.value.load(Ordering::SeqCst)
+    }
+}
+
+#[test]
+fn get_with_meta_gives_up_during_long_sets() {
+    let var: WithMeta<Blocking> = WithMeta::new();
+    let (entered, in_set) = mpsc::channel();
+    let (finish, finished) = mpsc::channel();
+    *ENTERED.lock().unwrap() = Some(entered);
+    *FINISH.lock().unwrap() = Some(finished);
+
+    thread::scope(|scope| {
+        scope.spawn(|| GetterSetter::set(&var, 7));
+        in_set.recv().unwrap();
+        // The value is written, the meta of its set is not
+        let Err(Inconsistent((value, meta))) = var.get_with_meta() else {
+            panic!("read during a set was consistent");
+        };
+        assert_eq!((value, meta.updates), (7, 0));
+        finish.send(()).unwrap();
+    });
+    let (value, meta) = var.get_with_meta().unwrap();
+    assert_eq!((value, meta.updates), (7, 1));
+}
+
+#[test]
+fn ring_buffers_with_meta() {
+    let tree = IoTree::new();
+    for v in [1.0, 2.0, 3.0, 4.0] {
+        GetterSetter::set(&tree.process.m_Imu.acc, v);
+    }
+    assert_eq!(tree.process.m_Imu.acc.get(), 4.0);
+    assert_eq!(tree.process.m_Imu.acc.get_array(), vec![2.0, 3.0, 4.0]);
+    assert_eq!(tree.process.m_Imu.acc.update_count(), 4);
+}
+
+#[test]
+fn registry_and_snapshots_update_meta() {
+    let tree = IoTree::new();
+    tree.set_by_path("process.Baro.altitude", Value::F32(50.0))
+        .unwrap();
+    assert_eq!(tree.process.m_Baro.altitude.update_count(), 1);
+
+    let snapshot = tree.snapshot().unwrap();
+    assert_eq!(snapshot.process.m_Baro.altitude, 50.0);
+    tree.restore(&snapshot);
+    assert_eq!(tree.process.m_Baro.altitude.get(), 50.0);
+    assert_eq!(tree.process.m_Baro.altitude.update_count(), 2);
+}
+
+#[test]
+fn stale_data_condition() {
+    init_callbacks();
+    let entry = &CALLBACKS.callbacks(IOTREE.process.m_Baro.altitude.id())[0];
+    let (condition, callback) = (&entry.condition, &entry.callback);
+
+    // Never set, so the altitude is stale from the start
+    assert!(condition());
+    GetterSetter::set(&IOTREE.process.m_Baro.altitude, 10.0);
+    assert!(!condition());
+
+    thread::sleep(Duration::from_millis(250));
+    assert!(condition());
+    callback();
+    assert!(IOTREE.control.baro_stale.get());
+}
@@ -0,0 +1,34 @@
+use aurora_hal::subscriptions::{subscribe_channel, Delivery};
+use aurora_hal::{init_io_tree, GetterSetter, PathRegistry, Value};
+
+init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+
+// Every test uses its own variables of IOTREE
+
+#[test]
+fn config_callbacks_fire_on_set_by_path() {
+    init_callbacks();
+    // Callbacks.toml: arms once the altitude is above 100
+    IOTREE
+        .set_by_path_str("process.Baro.altitude", "50.0")
+        .unwrap();
+    assert!(!IOTREE.control.armed.get());
+    IOTREE
+        .set_by_path_str("process.Baro.altitude", "120.5")
+        .unwrap();
+    assert!(IOTREE.control.armed.get());
+}
+
+#[test]
+fn subscribers_see_sets_by_path() {
+    let (_subscription, changes) =
+        subscribe_channel(&*IOTREE, "process.Gps.speed", Delivery::Immediate).unwrap();
+
+    IOTREE
+        .set_by_path("process.Gps.speed", Value::F64(1.5))
+        .unwrap();
+    let change = changes.try_recv().unwrap();
+    assert_eq!(change.path, "process.Gps.speed");
+    assert_eq!((change.old, change.new), (Value::F64(0.0), Value::F64(1.5)));
+    assert!(changes.try_recv().is_err());
+}
@@ -0,0 +1,38 @@
+use aurora_hal::{init_io_tree, read_consistent, update, GetterSetter, Inconsistent};
+
+init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+
+// The seqlock is shared by all trees, so the tests updating in a loop or for long have a binary of their own
+
+#[test]
+fn snapshots_are_consistent() {
+    let writer = std::thread::spawn(|| {
+        for i in 0..20_000 {
+            update(|| {
+                IOTREE.process.m_Gyro.x.set(i);
+                IOTREE.process.m_Gyro.y.set(-i);
+            });
+        }
+    });
+    while !writer.is_finished() {
+        // Copies which kept overlapping with updates are not consistent
+        if let Ok(snapshot) = IOTREE.snapshot() {
+            let gyro = snapshot.process.m_Gyro;
+            assert_eq!(gyro.x, -gyro.y);
+        }
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn reads_give_up_during_long_updates() {
+    let mut reads = 0;
+    let result = update(|| {
+        read_consistent(|| {
+            reads += 1;
+            reads
+        })
+    });
+    // Only the last attempt reads, its value is returned as best effort
+    assert_eq!(result, Err(Inconsistent(1)));
+}
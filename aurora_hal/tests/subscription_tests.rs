@@ -0,0 +1,113 @@
+use aurora_hal::subscriptions::{subscribe, subscribe_channel, Change, Delivery};
+use aurora_hal::{init_io_tree, set, PathError, Value};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+
+// Every test uses its own variables of IOTREE
+
+#[test]
+fn immediate_changes_of_a_table() {
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let subscription = {
+        let changes = changes.clone();
+        subscribe(&*IOTREE, "process.Gyro", Delivery::Immediate, move |c| {
+            changes.lock().unwrap().push(c.clone())
+        })
+        .unwrap()
+    };
+
+    set!(IOTREE.process.m_Gyro.x, 3);
+    set!(IOTREE.process.m_Gyro.y, -4);
+    set!(IOTREE.process.m_Gyro.x, 5);
+    {
+        let changes = changes.lock().unwrap();
+        let values: Vec<_> = changes
+            .iter()
+            .map(|c| (c.path, c.old.clone(), c.new.clone()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("process.Gyro.x", Value::I64(0), Value::I64(3)),
+                ("process.Gyro.y", Value::I64(0), Value::I64(-4)),
+                ("process.Gyro.x", Value::I64(3), Value::I64(5)),
+            ]
+        );
+        assert!(changes[0].timestamp <= changes[2].timestamp);
+    }
+
+    subscription.cancel();
+    set!(IOTREE.process.m_Gyro.x, 6);
+    assert_eq!(changes.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn changes_through_a_channel() {
+    let (_subscription, changes) =
+        subscribe_channel(&*IOTREE, "process.Gps.speed", Delivery::Immediate).unwrap();
+
+    set!(IOTREE.process.m_Gps.speed, 1.5);
+    set!(IOTREE.process.m_Gps.speed, 2.5);
+    let first = changes.try_recv().unwrap();
+    assert_eq!((first.old, first.new), (Value::F64(0.0), Value::F64(1.5)));
+    let second = changes.try_recv().unwrap();
+    assert_eq!((second.old, second.new), (Value::F64(1.5), Value::F64(2.5)));
+    assert!(changes.try_recv().is_err());
+}
+
+#[test]
+fn slow_subscribers_get_coalesced_changes() {
+    let changes: Arc<Mutex<Vec<Change>>> = Arc::new(Mutex::new(Vec::new()));
+    let _subscription = {
+        let changes = changes.clone();
+        subscribe(&*IOTREE, "process.Imu.acc", Delivery::Coalesced, move |c| {
+            changes.lock().unwrap().push(c.clone());
+            thread::sleep(Duration::from_millis(20));
+        })
+        .unwrap()
+    };
+
+    for i in 1..=100 {
+        set!(IOTREE.process.m_Imu.acc, i as f64);
+    }
+
+    let start = Instant::now();
+    while changes.lock().unwrap().last().map(|c| c.new.clone()) != Some(Value::F64(100.0)) {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        thread::sleep(Duration::from_millis(5));
+    }
+    let changes = changes.lock().unwrap();
+    assert!(changes.len() < 100);
+    // Merged changes still connect to each other
+    assert_eq!(changes[0].old, Value::F64(0.0));
+    for pair in changes.windows(2) {
+        assert_eq!(pair[0].new, pair[1].old);
+    }
+}
+
+#[test]
+fn cancelling_stops_coalesced_delivery() {
+    let (subscription, changes) =
+        subscribe_channel(&*IOTREE, "control", Delivery::Coalesced).unwrap();
+
+    set!(IOTREE.control.recovery_enabled, true);
+    let change = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(change.path, "control.recovery_enabled");
+    assert_eq!(change.new, Value::Bool(true));
+
+    // Waits for the delivery thread, which drops the sender
+    subscription.cancel();
+    set!(IOTREE.control.recovery_enabled, false);
+    assert!(changes.recv().is_err());
+}
+
+#[test]
+fn rejects_unknown_paths() {
+    assert_eq!(
+        subscribe(&*IOTREE, "process.Gy", Delivery::Immediate, |_| {}).err(),
+        Some(PathError::UnknownPath("process.Gy".to_string()))
+    );
+}
@@ -0,0 +1,203 @@
+use aurora_hal::telemetry::{
+    channel_schemas_from_toml, ChannelScheduler, Schema, SchemaField, TelemetryError,
+    FORMAT_VERSION,
+};
+use aurora_hal::{init_io_tree, GetterSetter, PathError, Value, ValueType};
+use std::time::{Duration, Instant};
+
+init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+
+#[test]
+fn schema_from_toml_matches_io_tree() {
+    let schema = Schema::from_io_tree_toml(include_str!("config/IoTree.toml")).unwrap();
+    assert_eq!(schema, Schema::of(&*IOTREE));
+    assert_eq!(schema.fields()[0].path, "process.Baro.altitude");
+}
+
+#[test]
+fn encodes_and_decodes_frames() {
+    let tree = IoTree::new();
+    tree.process.m_Baro.altitude.set(1234.5);
+    tree.process.m_Gyro.y.set(-7);
+    GetterSetter::set(&tree.process.m_Imu.acc, 9.81);
+    tree.control.armed.set(true);
+
+    let schema = Schema::of(&tree);
+    let frame = schema.encode(&tree).unwrap();
+    // altitude f32, speed f64, gyro 2 * i64, acc f64, 2 * bool
+    assert_eq!(frame.len(), 5 + 4 + 8 + 16 + 8 + 2);
+    assert_eq!(frame[0], FORMAT_VERSION);
+    assert_eq!(frame[1..5], schema.hash().to_le_bytes());
+
+    let values = schema.decode(&frame).unwrap();
+    assert_eq!(values[0], ("process.Baro.altitude", Value::F32(1234.5)));
+    assert_eq!(values[3], ("process.Gyro.y", Value::I64(-7)));
+    assert_eq!(values[4], ("process.Imu.acc", Value::F64(9.81)));
+    assert_eq!(values[5], ("control.armed", Value::Bool(true)));
+}
+
+#[test]
+fn encodes_subsets() {
+    let tree = IoTree::new();
+    tree.process.m_Gps.speed.set(3.5);
+    let schema = Schema::of(&tree);
+    let subset = schema
+        .subset(&["control.armed", "process.Gps.speed"])
+        .unwrap();
+    assert_ne!(subset.hash(), schema.hash());
+
+    let frame = subset.encode(&tree).unwrap();
+    assert_eq!(
+        subset.decode(&frame).unwrap(),
+        vec![
+            ("control.armed", Value::Bool(false)),
+            ("process.Gps.speed", Value::F64(3.5))
+        ]
+    );
+    assert!(matches!(
+        schema.subset(&["process.Gps"]),
+        Err(TelemetryError::Path(_))
+    ));
+}
+
+#[test]
+fn encodes_fields_listed_by_hand() {
+    let tree = IoTree::new();
+    tree.process.m_Gyro.x.set(3);
+    // Not in the order of the tree, so the fields are looked up by path
+    let schema = Schema::new(vec![
+        SchemaField {
+            path: "process.Gyro.x".to_string(),
+            value_type: ValueType::I64,
+        },
+        SchemaField {
+            path: "control.armed".to_string(),
+            value_type: ValueType::Bool,
+        },
+    ]);
+    let frame = schema.encode(&tree).unwrap();
+    assert_eq!(
+        schema.decode(&frame).unwrap(),
+        vec![
+            ("process.Gyro.x", Value::I64(3)),
+            ("control.armed", Value::Bool(false))
+        ]
+    );
+
+    let unknown = Schema::new(vec![SchemaField {
+        path: "process.Gyro.z".to_string(),
+        value_type: ValueType::I64,
+    }]);
+    assert!(matches!(
+        unknown.encode(&tree),
+        Err(TelemetryError::Path(PathError::UnknownPath(_)))
+    ));
+}
+
+#[test]
+fn rejects_foreign_and_damaged_frames() {
+    let tree = IoTree::new();
+    let schema = Schema::of(&tree);
+    let subset = schema.subset(&["process.Gps.speed"]).unwrap();
+    let frame = schema.encode(&tree).unwrap();
+
+    assert!(matches!(
+        subset.decode(&frame),
+        Err(TelemetryError::SchemaMismatch { .. })
+    ));
+    assert_eq!(
+        schema.decode(&frame[..frame.len() - 1]),
+        Err(TelemetryError::Truncated)
+    );
+    let mut longer = frame.clone();
+    longer.push(0);
+    assert_eq!(
+        schema.decode(&longer),
+        Err(TelemetryError::TrailingBytes(1))
+    );
+    let mut newer = frame;
+    newer[0] = FORMAT_VERSION + 1;
+    assert!(matches!(
+        schema.decode(&newer),
+        Err(TelemetryError::Version { .. })
+    ));
+}
+
+#[test]
+fn encodes_strings() {
+    let schema = Schema::from_io_tree_toml("[Control]\nstate = \"str\"\n").unwrap();
+    let frame = schema
+        .encode_values(&[Value::Str("Coast".to_string())])
+        .unwrap();
+    assert_eq!(frame[5..], [5, 0, b'C', b'o', b'a', b's', b't']);
+    assert_eq!(
+        schema.decode(&frame).unwrap(),
+        vec![("control.state", Value::Str("Coast".to_string()))]
+    );
+}
+
+#[test]
+fn generates_channels() {
+    let [attitude, status] = IoTree::TELEMETRY_CHANNELS else {
+        panic!("expected two channels");
+    };
+    assert_eq!(attitude.name, "attitude");
+    assert_eq!(attitude.rate, 20.0);
+    assert_eq!(
+        attitude.fields,
+        ["process.Gyro.x", "process.Gyro.y", "process.Imu.acc"]
+    );
+    assert_eq!((status.name, status.priority), ("status", 1));
+    assert_eq!(IoTree::TELEMETRY_BYTES_PER_SECOND, 29.0 * 20.0 + 10.0 * 2.5);
+
+    let tree = IoTree::new();
+    for channel in IoTree::TELEMETRY_CHANNELS {
+        let frame = channel.schema(&tree).unwrap().encode(&tree).unwrap();
+        assert_eq!(frame.len(), channel.frame_len);
+    }
+}
+
+#[test]
+fn sends_channels_at_their_rate() {
+    let tree = IoTree::new();
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+    let mut scheduler = ChannelScheduler::new(&tree, IoTree::TELEMETRY_CHANNELS, start).unwrap();
+    let names = |frames: Vec<(&'static str, Vec<u8>)>| -> Vec<&str> {
+        frames.into_iter().map(|(name, _)| name).collect()
+    };
+
+    assert_eq!(
+        names(scheduler.poll(&tree, start).unwrap()),
+        ["attitude", "status"]
+    );
+    assert_eq!(scheduler.next_due(), Some(at(50)));
+    assert!(scheduler.poll(&tree, at(10)).unwrap().is_empty());
+    assert_eq!(names(scheduler.poll(&tree, at(50)).unwrap()), ["attitude"]);
+    // Missed attitude frames are skipped
+    assert_eq!(
+        names(scheduler.poll(&tree, at(400)).unwrap()),
+        ["attitude", "status"]
+    );
+    assert_eq!(scheduler.next_due(), Some(at(450)));
+}
+
+#[test]
+fn ground_station_decodes_channels() {
+    let tree = IoTree::new();
+    tree.process.m_Baro.altitude.set(80.0);
+    let schemas = channel_schemas_from_toml(include_str!("config/IoTree.toml")).unwrap();
+    assert_eq!(schemas.len(), 2);
+
+    let status = &IoTree::TELEMETRY_CHANNELS[1];
+    let frame = status.schema(&tree).unwrap().encode(&tree).unwrap();
+    let (name, schema) = schemas
+        .iter()
+        .find(|(_, schema)| schema.decode(&frame).is_ok())
+        .unwrap();
+    assert_eq!(name, "status");
+    assert_eq!(
+        schema.decode(&frame).unwrap()[1],
+        ("process.Baro.altitude", Value::F32(80.0))
+    );
+}
@@ -1,9 +1,11 @@
 use aurora_hal;
-use aurora_hal::{set, ArrayGetter, GetterSetter, RingBuffer, CALLBACKS};
-use std::ops::Deref;
+use aurora_hal::{
+    register_callback, register_callback_with_priority, set, unregister_callback, ArrayGetter,
+    GetterSetter, Leaf, LeafId, RingBuffer, CALLBACKS,
+};
 use std::sync::atomic::Ordering::Release;
 use std::sync::atomic::{AtomicI16, AtomicU32};
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 
 #[test]
 fn getter_setter_atomic_test() {
@@ -34,7 +36,7 @@ fn getter_setter_string_test() {
 
 #[test]
 fn set_macro_test() {
-    static X: AtomicU32 = AtomicU32::new(0);
+    static X: Leaf<AtomicU32, 0> = Leaf::with_value(AtomicU32::new(0));
 
     set!(X, 1);
     assert_eq!(X.get(), 1);
@@ -42,18 +44,14 @@ fn set_macro_test() {
 
 #[test]
 fn callback_test() {
-    static Y: AtomicU32 = AtomicU32::new(0);
-    let mut v: Vec<(
-        Box<dyn Fn() -> bool + Send + Sync>,
-        Box<dyn Fn() + Send + Sync>,
-    )> = Vec::new();
-    v.push((
-        Box::new(|| true),
-        Box::new(|| {
+    static Y: Leaf<AtomicU32, 1> = Leaf::with_value(AtomicU32::new(0));
+    register_callback(
+        Y.id(),
+        || true,
+        || {
             Y.store(5, Release);
-        }),
-    ));
-    CALLBACKS.lock().unwrap().insert("Y".to_string(), v);
+        },
+    );
 
     set!(Y, 1);
     assert_eq!(Y.get(), 5);
@@ -61,20 +59,14 @@ fn callback_test() {
 
 #[test]
 fn callback_in_thread() {
-    static Z: AtomicU32 = AtomicU32::new(0);
-    {
-        let mut v: Vec<(
-            Box<dyn Fn() -> bool + Send + Sync>,
-            Box<dyn Fn() + Send + Sync>,
-        )> = Vec::new();
-        v.push((
-            Box::new(|| true),
-            Box::new(|| {
-                Z.store(5, Release);
-            }),
-        ));
-        CALLBACKS.lock().unwrap().insert("Z".to_string(), v);
-    }
+    static Z: Leaf<AtomicU32, 2> = Leaf::with_value(AtomicU32::new(0));
+    register_callback(
+        Z.id(),
+        || true,
+        || {
+            Z.store(5, Release);
+        },
+    );
 
     let thread1 = std::thread::spawn(|| {
         set!(Z, 1);
@@ -105,10 +97,32 @@ fn getter_setter_with_history() {
 
 #[test]
 fn callback_static() {
-    let mut v: Vec<(
-        Box<dyn Fn() -> bool + Send + Sync>,
-        Box<dyn Fn() + Send + Sync>,
-    )> = Vec::new();
-    v.push((Box::new(|| true), Box::new(|| println!("Test succeeded!"))));
-    CALLBACKS.lock().unwrap().insert("test".to_string(), v);
+    let id = register_callback(LeafId(4), || true, || println!("Test succeeded!"));
+    assert_eq!(CALLBACKS.callbacks(LeafId(4)).len(), 1);
+    assert!(unregister_callback(id));
+    assert!(!unregister_callback(id));
+    assert!(CALLBACKS.callbacks(LeafId(4)).is_empty());
+}
+
+#[test]
+fn callbacks_run_by_priority() {
+    static W: Leaf<AtomicU32, 3> = Leaf::with_value(AtomicU32::new(0));
+    static ORDER: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+    register_callback(W.id(), || true, || ORDER.lock().unwrap().push("first"));
+    register_callback(
+        W.id(),
+        || W.get() > 1,
+        || ORDER.lock().unwrap().push("above 1"),
+    );
+    register_callback_with_priority(W.id(), -1, || true, || ORDER.lock().unwrap().push("urgent"));
+    let last = register_callback(W.id(), || true, || ORDER.lock().unwrap().push("last"));
+
+    set!(W, 1);
+    assert_eq!(*ORDER.lock().unwrap(), vec!["urgent", "first", "last"]);
+
+    ORDER.lock().unwrap().clear();
+    unregister_callback(last);
+    set!(W, 2);
+    assert_eq!(*ORDER.lock().unwrap(), vec!["urgent", "first", "above 1"]);
 }
@@ -1,17 +1,100 @@
+use crate::event_queue::EventQueue;
+use crate::stats::{GenStats, StatsRecorder};
+
+use std::error::Error;
 use std::marker::Send;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
 
-pub trait EventGenerator<T: Send, U> {
+pub trait EventGenerator<T: Send> {
     type Handle: EventGenHandle;
-    fn start(self, send_handle: Sender<T>) -> Self::Handle;
+    /// Why the generator could not be started, [`Infallible`](std::convert::Infallible) for
+    /// generators which always start.
+    type Error: Error + Send + 'static;
+
+    /// Starts generating events and sending them to `send_handle`.
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error>;
+
+    /// Starts the generator sending to `queue`, which stops it together with the other
+    /// generators of the queue.
+    fn start_into(self, queue: &mut EventQueue<T>) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+        Self::Handle: Send + 'static,
+    {
+        let handle = self.start(queue.sender())?;
+        queue.manage(handle);
+        Ok(())
+    }
 }
 
 pub trait EventGenHandle {
-    fn stop(&mut self);
+    /// Signals the generator to stop and waits for it to shut down.
+    ///
+    /// Returns `Err` with the panic payload if the generator thread panicked.
+    /// Calling `stop` on an already stopped generator is a no-op and returns `Ok(())`.
+    fn stop(&mut self) -> thread::Result<()>;
+
+    /// Returns `true` once the generator has stopped producing events, either because it
+    /// was stopped, ran out of events or its receiver was dropped.
+    fn is_finished(&self) -> bool;
+
+    /// Returns statistics about the events the generator has sent so far.
+    fn stats(&self) -> GenStats;
+}
+
+/// Handle to a generator running on its own thread, used by generators that block on I/O and
+/// therefore cannot run on the shared [`Scheduler`](crate::scheduler::Scheduler).
+///
+/// The generator thread is expected to poll the stop flag it is given and return once it is set.
+pub struct ThreadGenHandle {
+    join_handle: Option<thread::JoinHandle<()>>,
+    stop_flag: Arc<AtomicBool>,
+    stats: Arc<StatsRecorder>,
+}
+
+impl ThreadGenHandle {
+    pub fn spawn<F>(generator: F) -> Self
+    where
+        F: FnOnce(Arc<AtomicBool>) + Send + 'static,
+    {
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop_flag_2 = stop_flag.clone();
+        let join_handle = thread::spawn(move || generator(stop_flag_2));
+
+        Self {
+            join_handle: Some(join_handle),
+            stop_flag,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Reports the statistics recorded by `stats`, usually the recorder of the generator's
+    /// [`CountingSender`](crate::stats::CountingSender).
+    pub fn with_stats(mut self, stats: Arc<StatsRecorder>) -> Self {
+        self.stats = stats;
+        self
+    }
 }
 
-impl EventGenHandle for () {
-    fn stop(&mut self) {
-        // Do nothing.
+impl EventGenHandle for ThreadGenHandle {
+    fn stop(&mut self) -> thread::Result<()> {
+        self.stop_flag.store(true, Ordering::Relaxed);
+        match self.join_handle.take() {
+            Some(join_handle) => join_handle.join(),
+            None => Ok(()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_none_or(thread::JoinHandle::is_finished)
+    }
+
+    fn stats(&self) -> GenStats {
+        self.stats.snapshot()
     }
 }
@@ -0,0 +1,113 @@
+//! A queue shared by several generators, e.g. all event sources of a state machine state.
+//!
+//! A state creates an [`EventQueue`] in `create_event_sources`, starts its generators into it
+//! with [`EventGenerator::start_into`] and returns the receiver to the `StateMachine`. Stopping or
+//! dropping the queue in `destroy_event_sources` stops all of them at once:
+//!
+//! ```
+//! use event_gen::event_generator::EventGenerator;
+//! use event_gen::event_queue::EventQueue;
+//! use event_gen::generators::one_shot_generator::OneShotGenerator;
+//! use event_gen::generators::tick_generator::TickGenerator;
+//! use std::time::Duration;
+//!
+//! let (mut queue, receiver) = EventQueue::new();
+//! OneShotGenerator { value: "entered" }.start_into(&mut queue).unwrap();
+//! TickGenerator {
+//!     min_duration: Duration::from_millis(10),
+//!     event_producer: |_now, _prev| "tick",
+//! }
+//! .start_into(&mut queue)
+//! .unwrap();
+//!
+//! assert_eq!(receiver.recv().unwrap(), "entered");
+//! assert_eq!(receiver.recv().unwrap(), "tick");
+//! assert!(queue.stop().is_ok());
+//! ```
+//!
+//! [`EventGenerator::start_into`]: crate::event_generator::EventGenerator::start_into
+
+use crate::event_generator::EventGenHandle;
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+pub struct EventQueue<T> {
+    send_handle: Sender<T>,
+    handles: Vec<Box<dyn EventGenHandle + Send>>,
+}
+
+impl<T> EventQueue<T> {
+    /// Creates an empty queue and the receiver for the events of its generators.
+    ///
+    /// The queue holds on to a sender to be able to start further generators, so the receiver
+    /// does not report a disconnect before the queue is dropped.
+    pub fn new() -> (Self, Receiver<T>) {
+        let (send_handle, receiver) = mpsc::channel();
+        let queue = Self {
+            send_handle,
+            handles: Vec::new(),
+        };
+        (queue, receiver)
+    }
+
+    /// A sender into the queue, e.g. for generators which are started with a custom scheduler.
+    pub fn sender(&self) -> Sender<T> {
+        self.send_handle.clone()
+    }
+
+    /// Stops the generator of `handle` together with the queue.
+    pub fn manage(&mut self, handle: impl EventGenHandle + Send + 'static) {
+        self.handles.push(Box::new(handle));
+    }
+
+    /// Returns `true` once all generators of the queue have finished.
+    pub fn is_finished(&self) -> bool {
+        self.handles.iter().all(|handle| handle.is_finished())
+    }
+
+    /// Stops all generators, returns the first panic of a generator thread.
+    pub fn stop(&mut self) -> thread::Result<()> {
+        let mut result = Ok(());
+        for mut handle in self.handles.drain(..) {
+            result = result.and(handle.stop());
+        }
+        result
+    }
+}
+
+impl<T> Drop for EventQueue<T> {
+    fn drop(&mut self) {
+        // Panics have already been reported by the panic hook, there is nobody to hand them to.
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_generator::EventGenerator;
+    use crate::generators::one_shot_generator::OneShotGenerator;
+    use crate::generators::tick_generator::TickGenerator;
+    use std::time::Duration;
+
+    #[test]
+    fn dropping_stops_generators() {
+        let (mut queue, r) = EventQueue::new();
+        OneShotGenerator { value: 0 }
+            .start_into(&mut queue)
+            .unwrap();
+        TickGenerator {
+            min_duration: Duration::from_millis(1),
+            event_producer: |_now, _prev| 1,
+        }
+        .start_into(&mut queue)
+        .unwrap();
+
+        assert_eq!(r.recv().unwrap(), 0);
+        assert_eq!(r.recv().unwrap(), 1);
+        assert!(!queue.is_finished());
+        drop(queue);
+        while r.recv().is_ok() {}
+    }
+}
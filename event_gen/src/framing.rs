@@ -0,0 +1,600 @@
+//! Splitting byte streams into frames and decoding frames into events.
+//!
+//! [`Framer`]s turn a stream of bytes into frames, [`FrameDecoder`]s turn the payload of a frame
+//! into a user event. The following framings are provided, each with a matching encoder:
+//! - [`LineFramer`]: newline terminated lines, optionally validating NMEA 0183 checksums
+//! - [`CobsFramer`]: Consistent Overhead Byte Stuffing with `0` as frame delimiter
+//! - [`SlipFramer`]: Serial Line Internet Protocol (RFC 1055)
+//! - [`LengthPrefixedFramer`]: sync marker, `u16` LE length, payload, `u16` LE [`crc16`] of length
+//!   and payload
+//!
+//! All framers recover from lost, extra or corrupted bytes: the delimiter based ones at the next
+//! delimiter, the length prefixed one at the next sync marker starting a valid frame.
+
+/// Longest frame the delimiter based framers buffer before rejecting it.
+pub const MAX_FRAME_LEN: usize = 4096;
+
+/// Reason why a received frame was not turned into an event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The frame is truncated, too long, wrongly encoded or its checksum does not match.
+    Malformed(&'static str),
+    /// The sequence number of the frame is not after `last`, the one of the last accepted
+    /// frame of the same session.
+    OutOfSequence { last: u32, received: u32 },
+    /// The frame belongs to a session which is not newer than `current`, the session of the last
+    /// accepted frame, e.g. a delayed or replayed frame of an earlier ground station run.
+    StaleSession { current: u32, received: u32 },
+    /// The decoder did not understand the payload. For framings without sequence numbers
+    /// `sequence` is the index of the frame in the stream.
+    Undecodable { sequence: u32 },
+}
+
+/// Decodes frame payloads into user events.
+pub trait FrameDecoder: Send + 'static {
+    type Event: Send + 'static;
+
+    /// Returns `None` if the payload is not a valid message, which rejects the frame.
+    fn decode(&mut self, payload: &[u8]) -> Option<Self::Event>;
+
+    /// Called for every rejected frame, the returned event (if any) is sent to the FSM.
+    fn rejected(&mut self, _rejection: Rejection) -> Option<Self::Event> {
+        None
+    }
+}
+
+/// Splits a byte stream into frames.
+pub trait Framer: Send + 'static {
+    /// Feeds the next byte of the stream, returns the payload of a frame once it is complete or
+    /// the reason why it was rejected.
+    fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, Rejection>>;
+
+    /// Returns a frame which is complete without further bytes, e.g. one found in the buffered
+    /// bytes after a corrupted frame. Called after every frame returned by [`push`](Self::push)
+    /// or `poll` until it returns `None`.
+    fn poll(&mut self) -> Option<Result<Vec<u8>, Rejection>> {
+        None
+    }
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x1021
+            };
+        }
+    }
+    crc
+}
+
+/// Buffer shared by the delimiter based framers, which drops frames longer than
+/// [`MAX_FRAME_LEN`].
+#[derive(Default)]
+struct FrameBuffer {
+    buf: Vec<u8>,
+    overflow: bool,
+}
+
+impl FrameBuffer {
+    fn push(&mut self, byte: u8) {
+        if self.buf.len() < MAX_FRAME_LEN {
+            self.buf.push(byte);
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    /// Ends the current frame, returns `None` for empty frames.
+    fn finish(&mut self) -> Option<Result<Vec<u8>, Rejection>> {
+        let frame = std::mem::take(&mut self.buf);
+        if std::mem::take(&mut self.overflow) {
+            Some(Err(Rejection::Malformed("frame too long")))
+        } else if frame.is_empty() {
+            None
+        } else {
+            Some(Ok(frame))
+        }
+    }
+}
+
+/// Newline terminated frames, a trailing `\r` is removed and empty lines are skipped.
+#[derive(Default)]
+pub struct LineFramer {
+    buffer: FrameBuffer,
+    nmea: bool,
+}
+
+impl LineFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accepts NMEA 0183 sentences (`$...*HH`) with a valid checksum.
+    pub fn nmea() -> Self {
+        Self {
+            nmea: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl Framer for LineFramer {
+    fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, Rejection>> {
+        if byte != b'\n' {
+            self.buffer.push(byte);
+            return None;
+        }
+        if self.buffer.buf.last() == Some(&b'\r') {
+            self.buffer.buf.pop();
+        }
+        let line = self.buffer.finish()?;
+        Some(line.and_then(|line| {
+            if self.nmea {
+                check_nmea(&line)?;
+            }
+            Ok(line)
+        }))
+    }
+}
+
+fn check_nmea(sentence: &[u8]) -> Result<(), Rejection> {
+    let (start, rest) = sentence
+        .split_first()
+        .ok_or(Rejection::Malformed("empty sentence"))?;
+    if *start != b'$' && *start != b'!' {
+        return Err(Rejection::Malformed(
+            "sentence does not start with '$' or '!'",
+        ));
+    }
+    let star = rest
+        .iter()
+        .position(|b| *b == b'*')
+        .ok_or(Rejection::Malformed("missing checksum"))?;
+    let expected = std::str::from_utf8(&rest[star + 1..])
+        .ok()
+        .filter(|hex| hex.len() == 2)
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        .ok_or(Rejection::Malformed("invalid checksum"))?;
+
+    if rest[..star].iter().fold(0, |acc, b| acc ^ b) == expected {
+        Ok(())
+    } else {
+        Err(Rejection::Malformed("checksum mismatch"))
+    }
+}
+
+/// COBS encoded frames delimited by `0`.
+#[derive(Default)]
+pub struct CobsFramer {
+    buffer: FrameBuffer,
+}
+
+impl CobsFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Framer for CobsFramer {
+    fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, Rejection>> {
+        if byte != 0 {
+            self.buffer.push(byte);
+            return None;
+        }
+        let encoded = self.buffer.finish()?;
+        Some(encoded.and_then(|encoded| {
+            cobs_decode(&encoded).ok_or(Rejection::Malformed("invalid COBS encoding"))
+        }))
+    }
+}
+
+fn cobs_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let code = usize::from(encoded[i]);
+        let block = encoded.get(i + 1..i + code)?;
+        decoded.extend_from_slice(block);
+        i += code;
+        if code < 0xFF && i < encoded.len() {
+            decoded.push(0);
+        }
+    }
+    Some(decoded)
+}
+
+/// COBS encodes `payload` and appends the `0` delimiter.
+pub fn cobs_encode(payload: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(payload.len() + payload.len() / 254 + 2);
+    let mut code_index = 0;
+    encoded.push(0);
+    for byte in payload {
+        if *byte != 0 {
+            encoded.push(*byte);
+        }
+        let block_len = encoded.len() - code_index;
+        if *byte == 0 || block_len == 0xFF {
+            encoded[code_index] = u8::try_from(block_len).unwrap_or(0xFF);
+            code_index = encoded.len();
+            encoded.push(0);
+        }
+    }
+    encoded[code_index] = u8::try_from(encoded.len() - code_index).unwrap_or(0xFF);
+    encoded.push(0);
+    encoded
+}
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// SLIP encoded frames delimited by `0xC0`.
+#[derive(Default)]
+pub struct SlipFramer {
+    buffer: FrameBuffer,
+    escaped: bool,
+    invalid_escape: bool,
+}
+
+impl SlipFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Framer for SlipFramer {
+    fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, Rejection>> {
+        match (byte, std::mem::take(&mut self.escaped)) {
+            (SLIP_END, _) => {
+                let frame = self.buffer.finish();
+                if std::mem::take(&mut self.invalid_escape) {
+                    return Some(Err(Rejection::Malformed("invalid SLIP escape")));
+                }
+                frame
+            }
+            (SLIP_ESC, false) => {
+                self.escaped = true;
+                None
+            }
+            (SLIP_ESC_END, true) => {
+                self.buffer.push(SLIP_END);
+                None
+            }
+            (SLIP_ESC_ESC, true) => {
+                self.buffer.push(SLIP_ESC);
+                None
+            }
+            (_, true) => {
+                self.invalid_escape = true;
+                None
+            }
+            (byte, false) => {
+                self.buffer.push(byte);
+                None
+            }
+        }
+    }
+}
+
+/// SLIP encodes `payload`, including the leading and trailing `END` bytes.
+pub fn slip_encode(payload: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(payload.len() + 2);
+    encoded.push(SLIP_END);
+    for byte in payload {
+        match *byte {
+            SLIP_END => encoded.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => encoded.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            byte => encoded.push(byte),
+        }
+    }
+    encoded.push(SLIP_END);
+    encoded
+}
+
+/// Marks the start of every length prefixed frame.
+pub const LENGTH_PREFIXED_SYNC: [u8; 2] = [0xA5, 0x5A];
+
+/// Frames consisting of the [`LENGTH_PREFIXED_SYNC`] marker, a `u16` LE payload length, the
+/// payload and a `u16` LE [`crc16`] over length and payload.
+///
+/// Bytes which do not start with the sync marker are skipped. When a frame fails its checksum or
+/// announces a payload longer than the maximum, only its first byte is dropped and the following
+/// bytes are searched for the next sync marker, so frames behind a lost, extra or corrupted byte
+/// are still received. Only the first rejection of such a search is reported.
+pub struct LengthPrefixedFramer {
+    buf: Vec<u8>,
+    max_payload_len: usize,
+    resyncing: bool,
+}
+
+impl Default for LengthPrefixedFramer {
+    fn default() -> Self {
+        Self::with_max_len(MAX_FRAME_LEN)
+    }
+}
+
+impl LengthPrefixedFramer {
+    /// Accepts payloads of up to [`MAX_FRAME_LEN`] bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects frames announcing a payload longer than `max_payload_len` bytes right away.
+    pub fn with_max_len(max_payload_len: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_payload_len,
+            resyncing: false,
+        }
+    }
+
+    /// Returns the next complete frame at the start of the buffer, dropping bytes which cannot
+    /// start a valid frame.
+    fn next_frame(&mut self) -> Option<Result<Vec<u8>, Rejection>> {
+        loop {
+            let synced = match self.buf.len() {
+                0 => return None,
+                1 => self.buf[0] == LENGTH_PREFIXED_SYNC[0],
+                _ => self.buf[..2] == LENGTH_PREFIXED_SYNC,
+            };
+            let reason = if !synced {
+                "missing sync marker"
+            } else if self.buf.len() < 4 {
+                return None;
+            } else {
+                let payload_len = usize::from(u16::from_le_bytes([self.buf[2], self.buf[3]]));
+                if payload_len > self.max_payload_len {
+                    "frame too long"
+                } else if self.buf.len() < payload_len + 6 {
+                    return None;
+                } else {
+                    let (body, checksum) = self.buf[2..payload_len + 6].split_at(payload_len + 2);
+                    if crc16(body).to_le_bytes() == checksum {
+                        let payload = body[2..].to_vec();
+                        self.buf.drain(..payload_len + 6);
+                        self.resyncing = false;
+                        return Some(Ok(payload));
+                    }
+                    "checksum mismatch"
+                }
+            };
+
+            // Continue with the next byte which could start a frame
+            let skip = self.buf[1..]
+                .iter()
+                .position(|byte| *byte == LENGTH_PREFIXED_SYNC[0])
+                .map_or(self.buf.len(), |i| i + 1);
+            self.buf.drain(..skip);
+            if !std::mem::replace(&mut self.resyncing, true) {
+                return Some(Err(Rejection::Malformed(reason)));
+            }
+        }
+    }
+}
+
+impl Framer for LengthPrefixedFramer {
+    fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, Rejection>> {
+        self.buf.push(byte);
+        self.next_frame()
+    }
+
+    fn poll(&mut self) -> Option<Result<Vec<u8>, Rejection>> {
+        self.next_frame()
+    }
+}
+
+/// Builds a length prefixed frame carrying `payload`.
+///
+/// # Panics
+///
+/// Will panic if the payload is longer than `u16::MAX` bytes.
+pub fn length_prefixed_encode(payload: &[u8]) -> Vec<u8> {
+    let len = u16::try_from(payload.len()).expect("Payload does not fit into a frame");
+    let mut frame = Vec::with_capacity(payload.len() + 6);
+    frame.extend_from_slice(&LENGTH_PREFIXED_SYNC);
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc16(&frame[2..]).to_le_bytes());
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(framer: &mut impl Framer, bytes: &[u8]) -> Vec<Result<Vec<u8>, Rejection>> {
+        let mut frames = Vec::new();
+        for byte in bytes {
+            let mut frame = framer.push(*byte);
+            while let Some(f) = frame {
+                frames.push(f);
+                frame = framer.poll();
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn splits_lines() {
+        let mut framer = LineFramer::new();
+        assert_eq!(
+            frames(&mut framer, b"ARM\r\n\nPING\n"),
+            vec![Ok(b"ARM".to_vec()), Ok(b"PING".to_vec())]
+        );
+    }
+
+    #[test]
+    fn checks_nmea_sentences() {
+        let mut framer = LineFramer::nmea();
+        let valid = b"$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76";
+        let mut stream = valid.to_vec();
+        stream.extend_from_slice(b"\r\n$GPGGA,092750.000*00\r\nGPGGA\r\n");
+
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Ok(valid.to_vec()),
+                Err(Rejection::Malformed("checksum mismatch")),
+                Err(Rejection::Malformed(
+                    "sentence does not start with '$' or '!'"
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_overlong_frames() {
+        let mut framer = LineFramer::new();
+        let mut stream = vec![b'a'; MAX_FRAME_LEN + 1];
+        stream.extend_from_slice(b"\nok\n");
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Err(Rejection::Malformed("frame too long")),
+                Ok(b"ok".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn cobs_round_trip() {
+        let long: Vec<u8> = (0..600).map(|i| (i % 7) as u8 + 1).collect();
+        let payloads: [&[u8]; 5] = [b"\x00", b"\x11\x22\x00\x33", b"\x11\x00\x00", b"abc", &long];
+        for payload in payloads.iter() {
+            let mut framer = CobsFramer::new();
+            assert_eq!(
+                frames(&mut framer, &cobs_encode(payload)),
+                vec![Ok(payload.to_vec())]
+            );
+        }
+        assert_eq!(
+            cobs_encode(b"\x11\x22\x00\x33"),
+            b"\x03\x11\x22\x02\x33\x00"
+        );
+
+        let mut framer = CobsFramer::new();
+        assert_eq!(
+            frames(&mut framer, b"\x05\x11\x00"),
+            vec![Err(Rejection::Malformed("invalid COBS encoding"))]
+        );
+    }
+
+    #[test]
+    fn slip_round_trip() {
+        let payload = [0x01, SLIP_END, 0x02, SLIP_ESC, 0x03];
+        let mut framer = SlipFramer::new();
+        assert_eq!(
+            frames(&mut framer, &slip_encode(&payload)),
+            vec![Ok(payload.to_vec())]
+        );
+        assert_eq!(
+            frames(&mut framer, &[0x01, SLIP_ESC, 0x02, SLIP_END]),
+            vec![Err(Rejection::Malformed("invalid SLIP escape"))]
+        );
+    }
+
+    #[test]
+    fn length_prefixed_round_trip() {
+        let mut framer = LengthPrefixedFramer::new();
+        let mut stream = length_prefixed_encode(b"hello");
+        let mut corrupted = length_prefixed_encode(b"world");
+        corrupted[5] ^= 0xFF;
+        stream.extend(corrupted);
+        stream.extend(length_prefixed_encode(b""));
+
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Ok(b"hello".to_vec()),
+                Err(Rejection::Malformed("checksum mismatch")),
+                Ok(Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn length_prefixed_skips_garbage_byte() {
+        let mut framer = LengthPrefixedFramer::new();
+        let mut stream = length_prefixed_encode(b"hello");
+        stream.push(LENGTH_PREFIXED_SYNC[0]);
+        stream.extend(length_prefixed_encode(b"world"));
+        stream.extend(length_prefixed_encode(b"again"));
+
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Ok(b"hello".to_vec()),
+                Err(Rejection::Malformed("missing sync marker")),
+                Ok(b"world".to_vec()),
+                Ok(b"again".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn length_prefixed_resyncs_after_corrupted_length() {
+        let mut framer = LengthPrefixedFramer::new();
+        let mut stream = length_prefixed_encode(b"hello");
+        stream[2] = 1;
+        stream.extend(length_prefixed_encode(b"world"));
+        stream.extend(length_prefixed_encode(b"again"));
+
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Err(Rejection::Malformed("checksum mismatch")),
+                Ok(b"world".to_vec()),
+                Ok(b"again".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn length_prefixed_rejects_long_frames() {
+        let mut framer = LengthPrefixedFramer::with_max_len(4);
+        let mut stream = length_prefixed_encode(b"hello");
+        stream.extend(length_prefixed_encode(b"ok"));
+
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Err(Rejection::Malformed("frame too long")),
+                Ok(b"ok".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn length_prefixed_finds_buffered_frames() {
+        // The corrupted length swallows the next frame, which is found again once the checksum
+        // of the corrupted frame fails.
+        let mut framer = LengthPrefixedFramer::new();
+        let mut stream = length_prefixed_encode(b"hello");
+        stream[2] = 20;
+        stream.extend(length_prefixed_encode(b"world"));
+        stream.extend(length_prefixed_encode(b"again"));
+        stream.extend(length_prefixed_encode(b"third"));
+
+        assert_eq!(
+            frames(&mut framer, &stream),
+            vec![
+                Err(Rejection::Malformed("checksum mismatch")),
+                Ok(b"world".to_vec()),
+                Ok(b"again".to_vec()),
+                Ok(b"third".to_vec()),
+            ]
+        );
+    }
+}
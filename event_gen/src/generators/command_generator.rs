@@ -0,0 +1,282 @@
+//! Turns line based console commands, e.g. typed into stdin during bench tests, into events.
+//!
+//! The standard input is read by a single thread for the whole process, which queues the lines
+//! for whichever [`CommandGenerator::stdin`] generator is running. Stopping such a generator joins
+//! its thread right away and lines typed while no generator runs, e.g. during a state change of
+//! the FSM, go to the next one.
+//!
+//! Reading from any other reader blocks until a line arrives, so its reader thread cannot be
+//! joined when the generator is stopped. Instead, stopping drops the sender right away and the
+//! reader thread exits once the next line arrives or the input is closed.
+
+use crate::event_generator::{EventGenHandle, EventGenerator, ThreadGenHandle};
+use crate::stats::{CountingSender, GenStats, StatsRecorder};
+
+use std::convert::Infallible;
+use std::io::{self, BufRead};
+use std::marker::Send;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// How often a generator waiting for console input checks whether it has been stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct CommandGenerator<R, T: Send> {
+    /// A [`BufRead`] or the [`Console`].
+    pub reader: R,
+    /// Parses a line without its line ending, lines parsed to `None` are ignored.
+    pub parser: fn(&str) -> Option<T>,
+}
+
+/// The standard input of the process, shared by all [`CommandGenerator::stdin`] generators.
+pub struct Console {
+    lines: &'static Mutex<Receiver<String>>,
+}
+
+impl<T: Send> CommandGenerator<Console, T> {
+    /// Reads commands from the standard input of the process.
+    pub fn stdin(parser: fn(&str) -> Option<T>) -> Self {
+        Self {
+            reader: Console {
+                lines: stdin_lines(),
+            },
+            parser,
+        }
+    }
+}
+
+/// Lines of the standard input, read by one thread for the whole process so that a stopped
+/// generator never takes a line meant for the next one.
+fn stdin_lines() -> &'static Mutex<Receiver<String>> {
+    static LINES: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("event_gen-stdin".to_string())
+            .spawn(move || {
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    match io::stdin().read_line(&mut line) {
+                        // The generators finish once the input is closed.
+                        Ok(0) => break,
+                        Ok(_) => {}
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    }
+                    if sender.send(line.clone()).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn the stdin reader thread");
+        Mutex::new(receiver)
+    })
+}
+
+fn parse_line<T>(parser: fn(&str) -> Option<T>, line: &str) -> Option<T> {
+    parser(line.trim_end_matches(&['\r', '\n'][..]))
+}
+
+pub struct CommandGenHandle<T> {
+    join_handle: Option<thread::JoinHandle<()>>,
+    send_handle: Arc<Mutex<Option<CountingSender<T>>>>,
+    stats: Arc<StatsRecorder>,
+}
+
+impl<T> EventGenHandle for CommandGenHandle<T> {
+    fn stop(&mut self) -> thread::Result<()> {
+        self.send_handle.lock().unwrap().take();
+        match self.join_handle.take() {
+            // Only join if that does not mean waiting for the next line.
+            Some(join_handle) if join_handle.is_finished() => join_handle.join(),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.send_handle.lock().unwrap().is_none()
+            || self
+                .join_handle
+                .as_ref()
+                .is_none_or(thread::JoinHandle::is_finished)
+    }
+
+    fn stats(&self) -> GenStats {
+        self.stats.snapshot()
+    }
+}
+
+impl<R, T> EventGenerator<T> for CommandGenerator<R, T>
+where
+    R: BufRead + Send + 'static,
+    T: 'static + Send,
+{
+    type Handle = CommandGenHandle<T>;
+    type Error = Infallible;
+    fn start(mut self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        let send_handle = CountingSender::new(send_handle);
+        let stats = send_handle.recorder();
+        let send_handle = Arc::new(Mutex::new(Some(send_handle)));
+        let thread_send_handle = send_handle.clone();
+
+        let join_handle = thread::spawn(move || {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                match self.reader.read_line(&mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+
+                let Some(event) = parse_line(self.parser, &line) else {
+                    continue;
+                };
+                let sender = thread_send_handle.lock().unwrap();
+                match sender.as_ref() {
+                    Some(sender) if sender.send(event).is_ok() => {}
+                    // Stopped or the receiver is gone
+                    _ => break,
+                }
+            }
+            thread_send_handle.lock().unwrap().take();
+        });
+
+        Ok(Self::Handle {
+            join_handle: Some(join_handle),
+            send_handle,
+            stats,
+        })
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for CommandGenerator<Console, T> {
+    type Handle = ThreadGenHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        let send_handle = CountingSender::new(send_handle);
+        let stats = send_handle.recorder();
+        let lines = self.reader.lines;
+        let parser = self.parser;
+
+        let handle = ThreadGenHandle::spawn(move |stop_flag| {
+            while !stop_flag.load(Ordering::Relaxed) {
+                // Lines stay queued for the next generator while none is running.
+                let line = match lines.lock().unwrap().recv_timeout(POLL_INTERVAL) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if let Some(event) = parse_line(parser, &line) {
+                    if send_handle.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(handle.with_stats(stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+    use std::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    enum Command {
+        Arm,
+        Abort,
+    }
+
+    fn parse(line: &str) -> Option<Command> {
+        match line.trim() {
+            "arm" => Some(Command::Arm),
+            "abort" => Some(Command::Abort),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        let (s, r) = mpsc::channel();
+        let mut handle = CommandGenerator {
+            reader: Cursor::new("arm\r\nlaunch\n\n abort \n"),
+            parser: parse,
+        }
+        .start(s)
+        .unwrap();
+
+        let commands: Vec<Command> = r.iter().collect();
+        assert_eq!(commands, vec![Command::Arm, Command::Abort]);
+        assert!(handle.is_finished());
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn stop_does_not_wait_for_input() {
+        // A pipe nobody writes to blocks forever, just like an idle console.
+        struct Blocking;
+        impl io::Read for Blocking {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                thread::park();
+                Ok(0)
+            }
+        }
+
+        let (s, r) = mpsc::channel();
+        let mut handle = CommandGenerator {
+            reader: BufReader::new(Blocking),
+            parser: parse,
+        }
+        .start(s)
+        .unwrap();
+
+        assert!(!handle.is_finished());
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+        assert!(r.recv().is_err());
+    }
+
+    #[test]
+    fn console_lines_go_to_the_running_generator() {
+        // Stands in for the stdin reader thread
+        let (lines, receiver) = mpsc::channel();
+        let shared: &'static Mutex<Receiver<String>> = Box::leak(Box::new(Mutex::new(receiver)));
+
+        let (s, r) = mpsc::channel();
+        let mut first = CommandGenerator {
+            reader: Console { lines: shared },
+            parser: parse,
+        }
+        .start(s)
+        .unwrap();
+        lines.send("arm\n".to_string()).unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Arm);
+        assert!(first.stop().is_ok());
+        assert!(first.is_finished());
+
+        // Typed while no generator is running
+        lines.send("abort\n".to_string()).unwrap();
+        let (s, r) = mpsc::channel();
+        let mut second = CommandGenerator {
+            reader: Console { lines: shared },
+            parser: parse,
+        }
+        .start(s)
+        .unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Abort);
+
+        drop(lines);
+        while !second.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(second.stop().is_ok());
+    }
+}
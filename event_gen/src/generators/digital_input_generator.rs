@@ -0,0 +1,230 @@
+//! Turns bouncing boolean inputs, e.g. launch detect breakwires, arming keys or umbilical
+//! disconnect switches, into debounced rising and falling edge events.
+//!
+//! The input is sampled every `poll_interval`. A new level is only accepted once every sample
+//! has shown it for at least `debounce`, so contact bounce and short glitches are ignored. The
+//! level read when the generator starts is taken as is and does not produce an edge.
+
+use crate::event_generator::EventGenerator;
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::CountingSender;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::marker::Send;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+pub enum DigitalSource {
+    /// Called for every sample on the timer thread, e.g. to read a pin through a GPIO driver.
+    /// It should return quickly, a blocking read delays all generators of the scheduler.
+    Closure(Box<dyn FnMut() -> bool + Send>),
+    /// A flag set by another part of the system, e.g. a leaf of the aurora_hal `IOTREE`.
+    Flag(&'static AtomicBool),
+    /// A file containing `0` or `1`, e.g. the `value` file of a pin exported through the Linux
+    /// sysfs GPIO interface (`/sys/class/gpio/gpio17/value`). The file is read on the timer
+    /// thread, files which may block, e.g. on a network file system, need a scheduler of their
+    /// own.
+    File(PathBuf),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DigitalEdge {
+    /// The input went from low to high, `at` is the time of the first high sample.
+    Rising { at: Instant },
+    /// The input went from high to low, `at` is the time of the first low sample.
+    Falling { at: Instant },
+}
+
+pub struct DigitalInputGenerator<T: Send> {
+    pub source: DigitalSource,
+    pub poll_interval: Duration,
+    pub debounce: Duration,
+    pub event_producer: fn(DigitalEdge) -> T,
+}
+
+pub type DigitalInputGenHandle = TimerHandle;
+
+enum Input {
+    Closure(Box<dyn FnMut() -> bool + Send>),
+    Flag(&'static AtomicBool),
+    File(File, String),
+}
+
+impl Input {
+    fn open(source: DigitalSource) -> io::Result<Self> {
+        Ok(match source {
+            DigitalSource::Closure(read) => Input::Closure(read),
+            DigitalSource::Flag(flag) => Input::Flag(flag),
+            DigitalSource::File(path) => Input::File(File::open(path)?, String::new()),
+        })
+    }
+
+    fn read(&mut self) -> io::Result<bool> {
+        match self {
+            Input::Closure(read) => Ok(read()),
+            Input::Flag(flag) => Ok(flag.load(Ordering::Acquire)),
+            Input::File(file, buf) => {
+                // sysfs attributes have to be read from the start every time.
+                file.seek(SeekFrom::Start(0))?;
+                buf.clear();
+                file.read_to_string(buf)?;
+                match buf.trim() {
+                    "0" => Ok(false),
+                    "1" => Ok(true),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("expected 0 or 1, got {buf:?}"),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl<T: 'static + Send> DigitalInputGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    ///
+    /// Fails if the source is a file which cannot be opened.
+    pub fn start_with(
+        self,
+        scheduler: &Scheduler,
+        send_handle: Sender<T>,
+    ) -> io::Result<DigitalInputGenHandle> {
+        let mut input = Input::open(self.source)?;
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let (poll_interval, debounce) = (self.poll_interval, self.debounce);
+        let event_producer = self.event_producer;
+
+        let mut level: Option<bool> = None;
+        // A level differing from `level` and the time it was first sampled
+        let mut candidate: Option<(bool, Instant)> = None;
+        let timer = scheduler.schedule(Instant::now(), move |now| {
+            match (input.read(), level) {
+                (Ok(sample), None) => level = Some(sample),
+                (Ok(sample), Some(current)) if sample == current => candidate = None,
+                (Ok(sample), Some(_)) => {
+                    let since = match candidate {
+                        Some((candidate_level, since)) if candidate_level == sample => since,
+                        _ => now,
+                    };
+                    candidate = Some((sample, since));
+
+                    if now.saturating_duration_since(since) >= debounce {
+                        level = Some(sample);
+                        candidate = None;
+                        let edge = if sample {
+                            DigitalEdge::Rising { at: since }
+                        } else {
+                            DigitalEdge::Falling { at: since }
+                        };
+                        send_handle.send(event_producer(edge)).ok()?;
+                    }
+                }
+                // A failed read is not a stable sample of the new level.
+                (Err(_), _) => candidate = None,
+            }
+            Some(now + poll_interval)
+        });
+        Ok(timer.with_stats(recorder))
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for DigitalInputGenerator<T> {
+    type Handle = DigitalInputGenHandle;
+    type Error = io::Error;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        self.start_with(Scheduler::global(), send_handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_generator::EventGenHandle;
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+
+    /// Plays back `samples`, repeating the last one forever.
+    fn scripted(samples: &[bool]) -> DigitalSource {
+        let mut samples: VecDeque<bool> = samples.iter().copied().collect();
+        DigitalSource::Closure(Box::new(move || {
+            if samples.len() > 1 {
+                samples.pop_front().unwrap()
+            } else {
+                samples[0]
+            }
+        }))
+    }
+
+    fn generator(source: DigitalSource, debounce: Duration) -> DigitalInputGenerator<DigitalEdge> {
+        DigitalInputGenerator {
+            source,
+            poll_interval: Duration::from_millis(1),
+            debounce,
+            event_producer: |edge| edge,
+        }
+    }
+
+    #[test]
+    fn ignores_bounces() {
+        let (s, r) = mpsc::channel();
+        let source = scripted(&[false, true, false, true, false, true, false]);
+        let mut handle = generator(source, Duration::from_millis(20))
+            .start(s)
+            .unwrap();
+
+        assert!(r.recv_timeout(Duration::from_millis(60)).is_err());
+        assert_eq!(handle.stats().events_sent, 0);
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn emits_debounced_edges() {
+        static BREAKWIRE: AtomicBool = AtomicBool::new(true);
+        let (s, r) = mpsc::channel();
+        let debounce = Duration::from_millis(10);
+        let mut handle = generator(DigitalSource::Flag(&BREAKWIRE), debounce)
+            .start(s)
+            .unwrap();
+        // Let the generator take the initial level
+        assert!(r.recv_timeout(Duration::from_millis(20)).is_err());
+
+        let broken = Instant::now();
+        BREAKWIRE.store(false, Ordering::Release);
+        let DigitalEdge::Falling { at } = r.recv().unwrap() else {
+            panic!("expected a falling edge");
+        };
+        assert!(at >= broken);
+        assert!(broken.elapsed() >= debounce);
+
+        BREAKWIRE.store(true, Ordering::Release);
+        assert!(matches!(r.recv().unwrap(), DigitalEdge::Rising { at: rising } if rising > at));
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn reads_sysfs_value_file() {
+        let path = std::env::temp_dir().join(format!("event_gen_gpio_{}", std::process::id()));
+        std::fs::write(&path, "0\n").unwrap();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator(DigitalSource::File(path.clone()), Duration::ZERO)
+            .start(s)
+            .unwrap();
+        assert!(r.recv_timeout(Duration::from_millis(20)).is_err());
+
+        std::fs::write(&path, "1\n").unwrap();
+        assert!(matches!(r.recv().unwrap(), DigitalEdge::Rising { .. }));
+        assert!(handle.stop().is_ok());
+        std::fs::remove_file(path).unwrap();
+
+        let (s, _r) = mpsc::channel();
+        let missing = PathBuf::from("/nonexistent/gpio17/value");
+        assert!(generator(DigitalSource::File(missing), Duration::ZERO)
+            .start(s)
+            .is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Wraps another generator and randomly drops, duplicates, delays or reorders its events.
+//!
+//! Whether a fault is injected is decided by a seeded random number generator, one decision per
+//! event in the order the events arrive, so a failing test can be reproduced from its seed.
+
+use crate::event_generator::{EventGenHandle, EventGenerator, ThreadGenHandle};
+use crate::rng::SeededRng;
+use crate::stats::{CountingSender, GenStats};
+
+use std::marker::Send;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the forwarding thread checks whether it has been stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Probabilities (between 0 and 1) of the faults applied to every event.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    pub drop_probability: f64,
+    pub duplicate_probability: f64,
+    /// Delayed events are sent after a random delay of at most `max_delay`.
+    pub delay_probability: f64,
+    pub max_delay: Duration,
+    /// Reordered events are held back and sent right after the next event.
+    pub reorder_probability: f64,
+}
+
+pub struct FaultInjector<G> {
+    pub inner: G,
+    pub faults: Faults,
+    pub seed: u64,
+}
+
+pub struct FaultInjectorHandle<H: EventGenHandle> {
+    inner: H,
+    forwarder: ThreadGenHandle,
+}
+
+impl<H: EventGenHandle> EventGenHandle for FaultInjectorHandle<H> {
+    fn stop(&mut self) -> thread::Result<()> {
+        let inner = self.inner.stop();
+        let forwarder = self.forwarder.stop();
+        inner.and(forwarder)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.forwarder.is_finished()
+    }
+
+    /// Statistics of the events forwarded after injecting faults, the ones of the wrapped
+    /// generator are available through [`FaultInjectorHandle::inner`].
+    fn stats(&self) -> GenStats {
+        self.forwarder.stats()
+    }
+}
+
+impl<H: EventGenHandle> FaultInjectorHandle<H> {
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+}
+
+impl<T, G> EventGenerator<T> for FaultInjector<G>
+where
+    T: 'static + Clone + Send,
+    G: EventGenerator<T>,
+{
+    type Handle = FaultInjectorHandle<G::Handle>;
+    type Error = G::Error;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        let (inner_sender, inner_receiver) = mpsc::channel();
+        let inner = self.inner.start(inner_sender)?;
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+
+        let mut forwarder = Forwarder {
+            faults: self.faults,
+            rng: SeededRng::new(self.seed),
+            held: None,
+            delayed: Vec::new(),
+            send_handle,
+        };
+        let forwarder = ThreadGenHandle::spawn(move |stop_flag| {
+            let mut inner_done = false;
+            while !stop_flag.load(Ordering::Relaxed) {
+                let Some(timeout) = forwarder.release_due() else {
+                    return;
+                };
+                if inner_done {
+                    if forwarder.delayed.is_empty() {
+                        return;
+                    }
+                    thread::sleep(timeout);
+                    continue;
+                }
+
+                match inner_receiver.recv_timeout(timeout) {
+                    Ok(event) => {
+                        if !forwarder.inject(event) {
+                            return;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        inner_done = true;
+                        if let Some(event) = forwarder.held.take() {
+                            if forwarder.send_handle.send(event).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        })
+        .with_stats(recorder);
+
+        Ok(Self::Handle { inner, forwarder })
+    }
+}
+
+struct Forwarder<T> {
+    faults: Faults,
+    rng: SeededRng,
+    held: Option<T>,
+    delayed: Vec<(Instant, T)>,
+    send_handle: CountingSender<T>,
+}
+
+impl<T: Clone> Forwarder<T> {
+    /// Applies faults to `event`, returns `false` once the receiver has been dropped.
+    fn inject(&mut self, event: T) -> bool {
+        if self.rng.chance(self.faults.drop_probability) {
+            return true;
+        }
+        let copies = if self.rng.chance(self.faults.duplicate_probability) {
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let event = event.clone();
+            if self.rng.chance(self.faults.delay_probability) {
+                let delay = self
+                    .rng
+                    .duration_between(Duration::ZERO, self.faults.max_delay);
+                self.delayed.push((Instant::now() + delay, event));
+            } else if self.held.is_none() && self.rng.chance(self.faults.reorder_probability) {
+                self.held = Some(event);
+            } else {
+                if self.send_handle.send(event).is_err() {
+                    return false;
+                }
+                if let Some(held) = self.held.take() {
+                    if self.send_handle.send(held).is_err() {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Sends all delayed events which are due, returns how long to wait for the next one or
+    /// `None` once the receiver has been dropped.
+    fn release_due(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        while let Some(index) = self
+            .delayed
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _))| *due <= now)
+            .min_by_key(|(_, (due, _))| *due)
+            .map(|(index, _)| index)
+        {
+            let (_, event) = self.delayed.remove(index);
+            self.send_handle.send(event).ok()?;
+        }
+
+        let next_due = self.delayed.iter().map(|(due, _)| *due).min();
+        Some(next_due.map_or(POLL_INTERVAL, |due| (due - now).min(POLL_INTERVAL)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::replay_generator::{
+        LogFormat, ReplayGenerator, ReplayItem, ReplaySpeed,
+    };
+    use crate::generators::tick_generator::TickGenerator;
+    use std::path::PathBuf;
+
+    fn numbers(faults: Faults, seed: u64) -> Vec<u32> {
+        let path = std::env::temp_dir().join(format!(
+            "event_gen_faults_{}_{}.csv",
+            std::process::id(),
+            seed
+        ));
+        let csv: String = (0..20).map(|i| format!("0.0,{}\n", i)).collect();
+        std::fs::write(&path, csv).unwrap();
+
+        let (s, r) = mpsc::channel();
+        let mut handle = FaultInjector {
+            inner: ReplayGenerator {
+                path: PathBuf::from(&path),
+                format: LogFormat::Csv,
+                speed: ReplaySpeed::AsFastAsPossible,
+                event_mapper: |item| match item {
+                    ReplayItem::Record(r) => Some(r.values[0] as u32),
+                    _ => None,
+                },
+            },
+            faults,
+            seed,
+        }
+        .start(s)
+        .unwrap();
+
+        let events = r.iter().collect();
+        assert!(handle.stop().is_ok());
+        std::fs::remove_file(path).unwrap();
+        events
+    }
+
+    #[test]
+    fn forwards_without_faults() {
+        assert_eq!(numbers(Faults::default(), 1), (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drops_and_duplicates() {
+        let dropped = Faults {
+            drop_probability: 1.0,
+            ..Faults::default()
+        };
+        assert!(numbers(dropped, 2).is_empty());
+
+        let duplicated = Faults {
+            duplicate_probability: 1.0,
+            ..Faults::default()
+        };
+        let expected: Vec<u32> = (0..20).flat_map(|i| vec![i, i]).collect();
+        assert_eq!(numbers(duplicated, 3), expected);
+    }
+
+    #[test]
+    fn reorders_events() {
+        let reordered = Faults {
+            reorder_probability: 1.0,
+            ..Faults::default()
+        };
+        let expected: Vec<u32> = (0..10).flat_map(|i| vec![2 * i + 1, 2 * i]).collect();
+        assert_eq!(numbers(reordered, 4), expected);
+    }
+
+    #[test]
+    fn delays_events() {
+        let delayed = Faults {
+            delay_probability: 1.0,
+            max_delay: Duration::from_millis(50),
+            ..Faults::default()
+        };
+        let mut events = numbers(delayed, 5);
+        assert_ne!(events, (0..20).collect::<Vec<_>>());
+        events.sort_unstable();
+        assert_eq!(events, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn is_reproducible() {
+        let faults = Faults {
+            drop_probability: 0.2,
+            duplicate_probability: 0.2,
+            reorder_probability: 0.2,
+            ..Faults::default()
+        };
+        assert_eq!(numbers(faults.clone(), 6), numbers(faults, 6));
+    }
+
+    #[test]
+    fn stops_inner_generator() {
+        let (s, r) = mpsc::channel();
+        let mut handle = FaultInjector {
+            inner: TickGenerator {
+                min_duration: Duration::from_millis(1),
+                event_producer: |_now, _prev| 42,
+            },
+            faults: Faults::default(),
+            seed: 0,
+        }
+        .start(s)
+        .unwrap();
+
+        assert_eq!(r.recv().unwrap(), 42);
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+        while r.recv().is_ok() {}
+
+        let forwarded = handle.stats().events_sent;
+        assert!(forwarded >= 1);
+        assert!(handle.inner().stats().events_sent >= forwarded);
+    }
+}
@@ -0,0 +1,525 @@
+//! Detects launch, burnout, apogee and landing from acceleration and barometric altitude
+//! histories, e.g. IoTree ring buffers read with `ArrayGetter::get_array`.
+//!
+//! Every `poll_interval` both sources are read and handed to a [`FlightDetector`], which looks at
+//! the newest samples of each window. The events are detected in flight order, each detector is
+//! only armed once the previous event has been emitted:
+//!
+//! * launch: the accelerations have been at or above `threshold` for `hold`,
+//! * burnout: the accelerations have been below `threshold` for `hold`,
+//! * apogee: the altitudes have been at least `descent` below the highest altitude seen since
+//!   launch for `hold`. Apogee is also checked before burnout was detected, a missed burnout is
+//!   then skipped,
+//! * landing: the altitudes have stayed within `tolerance` of each other for `hold`.
+//!
+//! Holds are measured with the timestamps of the samples, so they do not depend on the sample
+//! rate, and every event reports the time of the first sample of its hold. Windows have to cover
+//! at least `hold` plus `poll_interval`, otherwise the start of a hold may have dropped out of the
+//! window by the time the hold is detected and the event is reported late. Since windows of
+//! consecutive polls overlap, the detectors only depend on the newest samples and the altitude
+//! peak, so reading the same samples twice does not matter. The generator finishes after landing.
+
+use crate::event_generator::EventGenerator;
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::CountingSender;
+
+use std::convert::Infallible;
+use std::marker::Send;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub at: Instant,
+    pub value: f64,
+}
+
+/// Returns the history of a sensor value, oldest sample first. Called on the timer thread every
+/// poll, so it should only copy out samples which are already there instead of waiting for new
+/// ones.
+pub type SampleSource = Box<dyn FnMut() -> Vec<Sample> + Send>;
+
+/// Timestamps a history without timestamps, e.g. a ring buffer, which gets a new sample every
+/// `period`. The newest sample is taken to be from the time the history is read.
+pub fn sampled_every<F>(period: Duration, mut read: F) -> SampleSource
+where
+    F: FnMut() -> Vec<f64> + Send + 'static,
+{
+    Box::new(move || {
+        let now = Instant::now();
+        let values = read();
+        let count = values.len();
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| Sample {
+                at: now
+                    .checked_sub(period * (count - 1 - i) as u32)
+                    .unwrap_or(now),
+                value,
+            })
+            .collect()
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlightEvent {
+    Launch {
+        at: Instant,
+    },
+    Burnout {
+        at: Instant,
+    },
+    /// `altitude` is the highest altitude seen.
+    Apogee {
+        at: Instant,
+        altitude: f64,
+    },
+    /// `altitude` is the newest altitude sample.
+    Landing {
+        at: Instant,
+        altitude: f64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LaunchDetector {
+    pub threshold: f64,
+    pub hold: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BurnoutDetector {
+    pub threshold: f64,
+    pub hold: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ApogeeDetector {
+    pub descent: f64,
+    pub hold: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LandingDetector {
+    pub tolerance: f64,
+    pub hold: Duration,
+}
+
+/// Returns the time of the first sample of the newest run of samples meeting `condition`, if
+/// the run lasts at least `hold`.
+fn held(window: &[Sample], hold: Duration, condition: impl Fn(f64) -> bool) -> Option<Instant> {
+    let newest = window.last()?;
+    let first = window
+        .iter()
+        .rev()
+        .take_while(|sample| condition(sample.value))
+        .last()?;
+    (newest.at.saturating_duration_since(first.at) >= hold).then_some(first.at)
+}
+
+impl LaunchDetector {
+    fn detect(&self, acceleration: &[Sample]) -> Option<Instant> {
+        held(acceleration, self.hold, |a| a >= self.threshold)
+    }
+}
+
+impl BurnoutDetector {
+    fn detect(&self, acceleration: &[Sample]) -> Option<Instant> {
+        held(acceleration, self.hold, |a| a < self.threshold)
+    }
+}
+
+impl ApogeeDetector {
+    fn detect(&self, altitude: &[Sample], peak: f64) -> Option<Instant> {
+        held(altitude, self.hold, |h| h <= peak - self.descent)
+    }
+}
+
+impl LandingDetector {
+    fn detect(&self, altitude: &[Sample]) -> Option<Instant> {
+        let newest = altitude.last()?;
+        let (mut min, mut max) = (newest.value, newest.value);
+        let first = altitude
+            .iter()
+            .rev()
+            .take_while(|sample| {
+                min = min.min(sample.value);
+                max = max.max(sample.value);
+                max - min <= self.tolerance
+            })
+            .last()?;
+        (newest.at.saturating_duration_since(first.at) >= self.hold).then_some(first.at)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    Pad,
+    Boost { peak: f64 },
+    Coast { peak: f64 },
+    Descent,
+    Landed,
+}
+
+/// The detection state machine driving the [`FlightEventGenerator`].
+#[derive(Clone, Debug)]
+pub struct FlightDetector {
+    pub launch: LaunchDetector,
+    pub burnout: BurnoutDetector,
+    pub apogee: ApogeeDetector,
+    pub landing: LandingDetector,
+    phase: Phase,
+}
+
+impl FlightDetector {
+    pub fn new(
+        launch: LaunchDetector,
+        burnout: BurnoutDetector,
+        apogee: ApogeeDetector,
+        landing: LandingDetector,
+    ) -> Self {
+        Self {
+            launch,
+            burnout,
+            apogee,
+            landing,
+            phase: Phase::Pad,
+        }
+    }
+
+    /// Returns `true` once landing has been detected.
+    pub fn has_landed(&self) -> bool {
+        self.phase == Phase::Landed
+    }
+
+    /// Feeds the current windows of both sources, returns at most one event per call.
+    pub fn update(&mut self, acceleration: &[Sample], altitude: &[Sample]) -> Option<FlightEvent> {
+        let window_peak = altitude
+            .iter()
+            .map(|sample| sample.value)
+            .fold(f64::NEG_INFINITY, f64::max);
+        match self.phase {
+            Phase::Pad => {
+                let at = self.launch.detect(acceleration)?;
+                self.phase = Phase::Boost { peak: window_peak };
+                Some(FlightEvent::Launch { at })
+            }
+            Phase::Boost { peak } | Phase::Coast { peak } => {
+                let peak = peak.max(window_peak);
+                if let Some(at) = self.apogee.detect(altitude, peak) {
+                    self.phase = Phase::Descent;
+                    return Some(FlightEvent::Apogee { at, altitude: peak });
+                }
+                let burnout = match self.phase {
+                    Phase::Boost { .. } => self.burnout.detect(acceleration),
+                    _ => None,
+                };
+                if let Some(at) = burnout {
+                    self.phase = Phase::Coast { peak };
+                    return Some(FlightEvent::Burnout { at });
+                }
+                self.phase = match self.phase {
+                    Phase::Boost { .. } => Phase::Boost { peak },
+                    _ => Phase::Coast { peak },
+                };
+                None
+            }
+            Phase::Descent => {
+                let at = self.landing.detect(altitude)?;
+                self.phase = Phase::Landed;
+                let altitude = altitude.last().map_or(0.0, |sample| sample.value);
+                Some(FlightEvent::Landing { at, altitude })
+            }
+            Phase::Landed => None,
+        }
+    }
+}
+
+pub struct FlightEventGenerator<T: Send> {
+    pub acceleration: SampleSource,
+    pub altitude: SampleSource,
+    pub poll_interval: Duration,
+    pub detector: FlightDetector,
+    /// Called on the timer thread for every detected event, it should not block.
+    pub event_producer: fn(FlightEvent) -> T,
+}
+
+pub type FlightEventGenHandle = TimerHandle;
+
+impl<T: 'static + Send> FlightEventGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> FlightEventGenHandle {
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let FlightEventGenerator {
+            mut acceleration,
+            mut altitude,
+            poll_interval,
+            mut detector,
+            event_producer,
+        } = self;
+
+        let timer = scheduler.schedule(Instant::now(), move |now| {
+            if let Some(event) = detector.update(&acceleration(), &altitude()) {
+                send_handle.send(event_producer(event)).ok()?;
+            }
+            if detector.has_landed() {
+                return None;
+            }
+            Some(now + poll_interval)
+        });
+        timer.with_stats(recorder)
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for FlightEventGenerator<T> {
+    type Handle = FlightEventGenHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_generator::EventGenHandle;
+    use aurora_hal::{ArrayGetter, GetterSetter, RingBuffer};
+    use std::sync::mpsc;
+    use std::sync::RwLock;
+
+    const G: f64 = 9.81;
+    const DT: f64 = 0.01;
+
+    fn detector() -> FlightDetector {
+        FlightDetector::new(
+            LaunchDetector {
+                threshold: 3.0 * G,
+                hold: Duration::from_millis(100),
+            },
+            BurnoutDetector {
+                threshold: 0.5 * G,
+                hold: Duration::from_millis(100),
+            },
+            ApogeeDetector {
+                descent: 2.0,
+                hold: Duration::from_millis(40),
+            },
+            LandingDetector {
+                tolerance: 1.0,
+                hold: Duration::from_secs(1),
+            },
+        )
+    }
+
+    /// Deterministic sensor noise in `[-amplitude, amplitude)`.
+    fn noise(i: usize, amplitude: f64) -> f64 {
+        ((i * 7919) % 13) as f64 / 6.5 * amplitude - amplitude
+    }
+
+    /// Samples (acceleration, altitude) at 100 Hz: 1 s on the pad, 2 s of boost at 5 g, a
+    /// ballistic coast, free fall until the parachute limits the descent to 8 m/s and 3 s on the
+    /// ground. Returns the samples and the index of the apogee.
+    fn flight_profile() -> (Vec<(f64, f64)>, usize) {
+        let mut samples = Vec::new();
+        let (mut altitude, mut velocity) = (0.0, 0.0);
+        let mut apogee = 0;
+        let mut landed_at = None;
+        for i in 0.. {
+            let t = i as f64 * DT;
+            let acceleration = if t < 1.0 {
+                0.0
+            } else if t < 3.0 {
+                5.0 * G
+            } else if velocity > -8.0 {
+                -G
+            } else {
+                0.0
+            };
+            if t >= 1.0 && landed_at.is_none() {
+                velocity += acceleration * DT;
+                altitude += velocity * DT;
+                if velocity > 0.0 {
+                    apogee = i;
+                }
+                if altitude <= 0.0 {
+                    altitude = 0.0;
+                    landed_at = Some(i);
+                }
+            }
+            // The accelerometer measures the specific force, 1 g at rest or under the parachute
+            let measured = if landed_at.is_some() {
+                G
+            } else {
+                acceleration + G
+            };
+            samples.push((measured + noise(i, 0.5), altitude + noise(i + 5, 0.3)));
+            if landed_at.is_some_and(|landed| i >= landed + 300) {
+                return (samples, apogee);
+            }
+        }
+        unreachable!()
+    }
+
+    /// Time of the sample with index `i` of a 100 Hz profile starting at `start`.
+    fn time(start: Instant, i: usize) -> Instant {
+        start + Duration::from_millis(i as u64 * 10)
+    }
+
+    /// Timestamps `values` as 100 Hz samples, the first one having index `first`.
+    fn timed(start: Instant, first: usize, values: &[f64]) -> Vec<Sample> {
+        (first..)
+            .zip(values)
+            .map(|(i, &value)| Sample {
+                at: time(start, i),
+                value,
+            })
+            .collect()
+    }
+
+    /// Runs the detector over 128 sample windows, returns the events and the sample index they
+    /// were emitted at.
+    fn detect(start: Instant, samples: &[(f64, f64)]) -> Vec<(FlightEvent, usize)> {
+        let mut detector = detector();
+        let mut events = Vec::new();
+        for i in 0..samples.len() {
+            let first = i.saturating_sub(127);
+            let window = &samples[first..=i];
+            let acceleration: Vec<f64> = window.iter().map(|s| s.0).collect();
+            let altitude: Vec<f64> = window.iter().map(|s| s.1).collect();
+            if let Some(event) = detector.update(
+                &timed(start, first, &acceleration),
+                &timed(start, first, &altitude),
+            ) {
+                events.push((event, i));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn detects_flight_events_in_order() {
+        let (samples, apogee) = flight_profile();
+        let start = Instant::now();
+        let events = detect(start, &samples);
+        assert_eq!(events.len(), 4, "{events:?}");
+
+        // Launch at 1 s, burnout at 3 s, both confirmed after 100 ms
+        let launch = FlightEvent::Launch {
+            at: time(start, 100),
+        };
+        assert_eq!(events[0], (launch, 110));
+        let burnout = FlightEvent::Burnout {
+            at: time(start, 300),
+        };
+        assert_eq!(events[1], (burnout, 310));
+
+        let (FlightEvent::Apogee { at, altitude }, index) = events[2] else {
+            panic!("expected apogee, got {:?}", events[2]);
+        };
+        let true_apogee = samples[apogee].1;
+        assert!((altitude - true_apogee).abs() < 1.0);
+        // 2 m of descent take about 0.64 s of free fall
+        assert!(index > apogee + 50 && index < apogee + 100);
+        assert_eq!(at, time(start, index - 4));
+
+        let (FlightEvent::Landing { at, altitude }, index) = events[3] else {
+            panic!("expected landing, got {:?}", events[3]);
+        };
+        assert!(altitude.abs() < 1.0);
+        assert!(index < samples.len() - 100);
+        assert_eq!(at, time(start, index - 100));
+    }
+
+    #[test]
+    fn ignores_short_acceleration_spikes() {
+        // A knock against the rail is far above the launch threshold, but only for 5 samples
+        let samples: Vec<(f64, f64)> = (0..500)
+            .map(|i| {
+                let acceleration = if (200..205).contains(&i) { 10.0 * G } else { G };
+                (acceleration + noise(i, 0.5), noise(i, 0.3))
+            })
+            .collect();
+        assert!(detect(Instant::now(), &samples).is_empty());
+    }
+
+    #[test]
+    fn holds_do_not_depend_on_the_sample_rate() {
+        let start = Instant::now();
+        let boost_at = start + Duration::from_millis(500);
+        for period in [Duration::from_millis(1), Duration::from_millis(10)] {
+            let samples: Vec<Sample> = (0..)
+                .map(|i| start + period * i)
+                .take_while(|&at| at < start + Duration::from_secs(1))
+                .map(|at| Sample {
+                    at,
+                    value: if at < boost_at { G } else { 5.0 * G },
+                })
+                .collect();
+
+            let mut detector = detector();
+            let launch = (1..=samples.len()).find_map(|newest| {
+                let window = &samples[..newest];
+                let event = detector.update(window, &[])?;
+                Some((event, window[newest - 1].at))
+            });
+            let detected_at = boost_at + Duration::from_millis(100);
+            assert_eq!(
+                launch,
+                Some((FlightEvent::Launch { at: boost_at }, detected_at))
+            );
+        }
+    }
+
+    #[test]
+    fn detects_apogee_without_burnout() {
+        // A sustainer burning until apogee never shows burnout
+        let mut detector = detector();
+        let start = Instant::now();
+        let boost = timed(start, 0, &[5.0 * G; 11]);
+        assert!(matches!(
+            detector.update(&boost, &timed(start, 8, &[0.0, 1.0, 2.0])),
+            Some(FlightEvent::Launch { .. })
+        ));
+        let altitude = timed(start, 8, &[100.0, 120.0, 130.0]);
+        assert_eq!(detector.update(&boost, &altitude), None);
+        let altitude = timed(start, 10, &[130.0, 127.0, 126.0, 125.0, 124.0, 123.0]);
+        assert_eq!(
+            detector.update(&boost, &altitude),
+            Some(FlightEvent::Apogee {
+                at: time(start, 11),
+                altitude: 130.0
+            })
+        );
+    }
+
+    #[test]
+    fn reads_ring_buffers() {
+        static ACCELERATION: RwLock<RingBuffer<f64, 16>> = RwLock::new(RingBuffer::new([G; 16]));
+        static ALTITUDE: RwLock<RingBuffer<f64, 16>> = RwLock::new(RingBuffer::new([0.0; 16]));
+
+        let (s, r) = mpsc::channel();
+        let mut handle = FlightEventGenerator {
+            acceleration: sampled_every(Duration::from_millis(10), || ACCELERATION.get_array()),
+            altitude: sampled_every(Duration::from_millis(10), || ALTITUDE.get_array()),
+            poll_interval: Duration::from_millis(1),
+            detector: detector(),
+            event_producer: |event| event,
+        }
+        .start(s)
+        .unwrap();
+        assert!(r.recv_timeout(Duration::from_millis(20)).is_err());
+
+        for _ in 0..16 {
+            GetterSetter::set(&ACCELERATION, 5.0 * G);
+        }
+        assert!(matches!(r.recv().unwrap(), FlightEvent::Launch { .. }));
+        for _ in 0..16 {
+            GetterSetter::set(&ACCELERATION, 0.0);
+        }
+        assert!(matches!(r.recv().unwrap(), FlightEvent::Burnout { .. }));
+        assert_eq!(handle.stats().events_sent, 2);
+        assert!(handle.stop().is_ok());
+    }
+}
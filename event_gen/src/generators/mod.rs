@@ -1,2 +1,14 @@
+pub mod command_generator;
+pub mod digital_input_generator;
+pub mod fault_injector;
+pub mod flight_event_generator;
+pub mod network_generator;
 pub mod one_shot_generator;
+pub mod random_generator;
+pub mod replay_generator;
+#[cfg(unix)]
+pub mod signal_generator;
+pub mod stream_generator;
 pub mod tick_generator;
+pub mod timeline_generator;
+pub mod watchdog_generator;
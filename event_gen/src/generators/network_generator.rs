@@ -0,0 +1,606 @@
+//! Turns ground station commands received over UDP or TCP into events.
+//!
+//! Every command is sent as a frame:
+//!
+//! | field    | size     | description                                      |
+//! |----------|----------|--------------------------------------------------|
+//! | session  | 4 bytes  | `u32` LE, newer for every start of the sender    |
+//! | sequence | 4 bytes  | `u32` LE, must increase with every frame         |
+//! | length   | 2 bytes  | `u16` LE, length of the payload                  |
+//! | payload  | length   | decoded into an event by a [`FrameDecoder`]      |
+//! | checksum | 2 bytes  | `u16` LE, [`crc16`] over all of the above        |
+//!
+//! Over UDP every datagram carries exactly one frame, over TCP the frames are sent back to back.
+//! Frames with a bad checksum or length, with a sequence number not after the one of the last
+//! accepted frame of the same session, or with a payload the decoder does not understand are
+//! rejected. Sequence numbers are compared with serial number arithmetic, so a sequence may wrap
+//! around from `u32::MAX` to 0.
+//!
+//! Session ids are compared with serial number arithmetic as well. A frame of a newer session,
+//! e.g. from a restarted ground station, starts a new sequence, while frames of older sessions are
+//! rejected, so delayed or replayed frames of an earlier run never turn into commands. This also
+//! holds across TCP connections. [`CommandClient`] sends frames in this format and derives its
+//! session from the current time when it is created, so a session is newer than the ones of all
+//! clients created before, as long as they are less than 24 days apart.
+
+use crate::event_generator::{EventGenerator, ThreadGenHandle};
+use crate::framing::{crc16, FrameDecoder, Rejection};
+use crate::stats::CountingSender;
+
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const HEADER_LEN: usize = 10;
+const CHECKSUM_LEN: usize = 2;
+/// Largest possible frame, used as the receive buffer size.
+pub const MAX_FRAME_LEN: usize = HEADER_LEN + u16::MAX as usize + CHECKSUM_LEN;
+/// How often the generator thread checks whether it has been stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Session, sequence number and payload of a valid frame.
+type Frame<'a> = Result<(u32, u32, &'a [u8]), Rejection>;
+
+enum Listener {
+    Udp(UdpSocket),
+    Tcp(TcpListener),
+}
+
+pub struct NetworkGenerator<D: FrameDecoder> {
+    listener: Listener,
+    decoder: D,
+}
+
+impl<D: FrameDecoder> NetworkGenerator<D> {
+    /// Listens for datagrams on `address`.
+    pub fn udp(address: impl ToSocketAddrs, decoder: D) -> io::Result<Self> {
+        Ok(Self {
+            listener: Listener::Udp(UdpSocket::bind(address)?),
+            decoder,
+        })
+    }
+
+    /// Listens for TCP connections on `address`, one connection is served at a time.
+    pub fn tcp(address: impl ToSocketAddrs, decoder: D) -> io::Result<Self> {
+        Ok(Self {
+            listener: Listener::Tcp(TcpListener::bind(address)?),
+            decoder,
+        })
+    }
+
+    /// The address the generator listens on, useful when binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.listener {
+            Listener::Udp(socket) => socket.local_addr(),
+            Listener::Tcp(listener) => listener.local_addr(),
+        }
+    }
+}
+
+impl<D: FrameDecoder> EventGenerator<D::Event> for NetworkGenerator<D> {
+    type Handle = ThreadGenHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<D::Event>) -> Result<Self::Handle, Self::Error> {
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let mut session = Session {
+            decoder: self.decoder,
+            last: None,
+            send_handle,
+        };
+        let listener = self.listener;
+
+        let handle = ThreadGenHandle::spawn(move |stop_flag| {
+            // Socket errors end the generator the same way a dropped receiver does.
+            let _ = match listener {
+                Listener::Udp(socket) => serve_udp(&socket, &mut session, &stop_flag),
+                Listener::Tcp(listener) => serve_tcp(&listener, &mut session, &stop_flag),
+            };
+        });
+        Ok(handle.with_stats(recorder))
+    }
+}
+
+struct Session<D: FrameDecoder> {
+    decoder: D,
+    /// Session and sequence number of the last accepted frame.
+    last: Option<(u32, u32)>,
+    send_handle: CountingSender<D::Event>,
+}
+
+impl<D: FrameDecoder> Session<D> {
+    /// Returns `false` once the receiver has been dropped.
+    fn handle(&mut self, frame: Frame<'_>) -> bool {
+        let event = match frame
+            .and_then(|(session, sequence, payload)| self.accept(session, sequence, payload))
+        {
+            Ok(event) => Some(event),
+            Err(rejection) => self.decoder.rejected(rejection),
+        };
+        match event {
+            Some(event) => self.send_handle.send(event).is_ok(),
+            None => true,
+        }
+    }
+
+    fn accept(
+        &mut self,
+        session: u32,
+        sequence: u32,
+        payload: &[u8],
+    ) -> Result<D::Event, Rejection> {
+        if let Some((current, last)) = self.last {
+            if session != current && !is_after(session, current) {
+                return Err(Rejection::StaleSession {
+                    current,
+                    received: session,
+                });
+            }
+            if session == current && !is_after(sequence, last) {
+                return Err(Rejection::OutOfSequence {
+                    last,
+                    received: sequence,
+                });
+            }
+        }
+        let event = self
+            .decoder
+            .decode(payload)
+            .ok_or(Rejection::Undecodable { sequence })?;
+        self.last = Some((session, sequence));
+        Ok(event)
+    }
+}
+
+/// Serial number arithmetic (RFC 1982): `sequence` is after `last` if it is at most `2^31 - 1`
+/// ahead of it, wrapping around at `u32::MAX`. Used for sequence numbers and session ids.
+fn is_after(sequence: u32, last: u32) -> bool {
+    (sequence.wrapping_sub(last) as i32) > 0
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+fn serve_udp<D: FrameDecoder>(
+    socket: &UdpSocket,
+    session: &mut Session<D>,
+    stop_flag: &AtomicBool,
+) -> io::Result<()> {
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut buf = vec![0; MAX_FRAME_LEN];
+
+    while !stop_flag.load(Ordering::Relaxed) {
+        let len = match socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => return Err(e),
+        };
+        let frame = match parse_frame(&buf[..len]) {
+            Some((consumed, _)) if consumed != len => {
+                Err(Rejection::Malformed("trailing bytes after frame"))
+            }
+            Some((_, frame)) => frame,
+            None => Err(Rejection::Malformed("truncated frame")),
+        };
+        if !session.handle(frame) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn serve_tcp<D: FrameDecoder>(
+    listener: &TcpListener,
+    session: &mut Session<D>,
+    stop_flag: &AtomicBool,
+) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let mut connection: Option<TcpStream> = None;
+    let mut buf = Vec::new();
+    let mut chunk = vec![0; MAX_FRAME_LEN];
+
+    while !stop_flag.load(Ordering::Relaxed) {
+        let Some(stream) = connection.as_mut() else {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+                    // The session and sequence carry over, a new connection has to use a newer
+                    // session to be accepted, just like a new client does.
+                    connection = Some(stream);
+                    buf.clear();
+                }
+                Err(e) if is_timeout(&e) => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e),
+            }
+            continue;
+        };
+
+        match stream.read(&mut chunk) {
+            Ok(0) => connection = None,
+            Ok(len) => buf.extend_from_slice(&chunk[..len]),
+            Err(e) if is_timeout(&e) => continue,
+            // The ground station can reconnect.
+            Err(_) => connection = None,
+        }
+
+        let mut consumed = 0;
+        while let Some((len, frame)) = parse_frame(&buf[consumed..]) {
+            consumed += len;
+            let corrupted = matches!(frame, Err(Rejection::Malformed(_)));
+            if !session.handle(frame) {
+                return Ok(());
+            }
+            if corrupted {
+                // There is no way to find the start of the next frame in the stream, force
+                // the ground station to reconnect instead.
+                connection = None;
+                consumed = buf.len();
+                break;
+            }
+        }
+        buf.drain(..consumed);
+    }
+    Ok(())
+}
+
+/// Parses the frame at the start of `buf`.
+///
+/// Returns `None` if `buf` does not contain a complete frame yet, otherwise the length of the
+/// frame in bytes and either its sequence number and payload or the reason it is rejected.
+fn parse_frame(buf: &[u8]) -> Option<(usize, Frame<'_>)> {
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+    let session = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let sequence = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    let payload_len = usize::from(u16::from_le_bytes([buf[8], buf[9]]));
+    let frame_len = HEADER_LEN + payload_len + CHECKSUM_LEN;
+    if buf.len() < frame_len {
+        return None;
+    }
+
+    let (body, checksum) = buf[..frame_len].split_at(HEADER_LEN + payload_len);
+    if crc16(body).to_le_bytes() != checksum {
+        return Some((frame_len, Err(Rejection::Malformed("checksum mismatch"))));
+    }
+    Some((frame_len, Ok((session, sequence, &body[HEADER_LEN..]))))
+}
+
+/// Builds a frame carrying `payload`.
+///
+/// # Panics
+///
+/// Will panic if the payload is longer than `u16::MAX` bytes.
+pub fn encode_frame(session: u32, sequence: u32, payload: &[u8]) -> Vec<u8> {
+    let len = u16::try_from(payload.len()).expect("Payload does not fit into a frame");
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + CHECKSUM_LEN);
+    frame.extend_from_slice(&session.to_le_bytes());
+    frame.extend_from_slice(&sequence.to_le_bytes());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc16(&frame).to_le_bytes());
+    frame
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Sends framed commands to a [`NetworkGenerator`], e.g. from ground station tooling or tests.
+pub struct CommandClient {
+    connection: Connection,
+    session: u32,
+    next_sequence: u32,
+}
+
+/// A session id newer than the ones of all clients created before, also across restarts of the
+/// ground station: the milliseconds since the Unix epoch, wrapping around at `u32::MAX`.
+fn new_session() -> u32 {
+    static LAST: Mutex<Option<u32>> = Mutex::new(None);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u32);
+    let mut last = LAST.lock().unwrap();
+    // Clients created within the same millisecond still get newer sessions.
+    let session = match *last {
+        Some(last) if !is_after(now, last) => last.wrapping_add(1),
+        _ => now,
+    };
+    *last = Some(session);
+    session
+}
+
+impl CommandClient {
+    pub fn udp(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(address)?;
+        Ok(Self {
+            connection: Connection::Udp(socket),
+            session: new_session(),
+            next_sequence: 0,
+        })
+    }
+
+    pub fn tcp(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            connection: Connection::Tcp(TcpStream::connect(address)?),
+            session: new_session(),
+            next_sequence: 0,
+        })
+    }
+
+    /// The session the frames of this client are sent with.
+    pub fn session(&self) -> u32 {
+        self.session
+    }
+
+    /// Sends `payload` with the next sequence number.
+    pub fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        let frame = encode_frame(self.session, self.next_sequence, payload);
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.send_raw(&frame)
+    }
+
+    /// Sends `bytes` as is, which allows sending malformed or replayed frames.
+    pub fn send_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.connection {
+            Connection::Udp(socket) => socket.send(bytes).map(|_| ()),
+            Connection::Tcp(stream) => stream.write_all(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_generator::EventGenHandle;
+    use std::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    enum Command {
+        Arm,
+        Abort,
+        Ping,
+        Rejected(Rejection),
+    }
+
+    struct Decoder;
+
+    impl FrameDecoder for Decoder {
+        type Event = Command;
+
+        fn decode(&mut self, payload: &[u8]) -> Option<Command> {
+            match payload {
+                b"ARM" => Some(Command::Arm),
+                b"ABORT" => Some(Command::Abort),
+                b"PING" => Some(Command::Ping),
+                _ => None,
+            }
+        }
+
+        fn rejected(&mut self, rejection: Rejection) -> Option<Command> {
+            Some(Command::Rejected(rejection))
+        }
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = encode_frame(3, 7, b"PING");
+        assert_eq!(
+            parse_frame(&frame),
+            Some((frame.len(), Ok((3, 7, &b"PING"[..]))))
+        );
+        assert_eq!(parse_frame(&frame[..frame.len() - 1]), None);
+
+        let mut corrupted = frame.clone();
+        corrupted[HEADER_LEN] ^= 0xFF;
+        assert_eq!(
+            parse_frame(&corrupted),
+            Some((frame.len(), Err(Rejection::Malformed("checksum mismatch"))))
+        );
+    }
+
+    #[test]
+    fn receives_udp_commands() {
+        let generator = NetworkGenerator::udp("127.0.0.1:0", Decoder).unwrap();
+        let address = generator.local_addr().unwrap();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        let mut client = CommandClient::udp(address).unwrap();
+        client.send(b"ARM").unwrap();
+        client.send(b"LAUNCH").unwrap();
+        let session = client.session();
+        client
+            .send_raw(&encode_frame(session, 0, b"ABORT"))
+            .unwrap();
+        client
+            .send_raw(&encode_frame(session, 9, b"PING")[..5])
+            .unwrap();
+        client.send(b"ABORT").unwrap();
+
+        assert_eq!(r.recv().unwrap(), Command::Arm);
+        assert_eq!(
+            r.recv().unwrap(),
+            Command::Rejected(Rejection::Undecodable { sequence: 1 })
+        );
+        assert_eq!(
+            r.recv().unwrap(),
+            Command::Rejected(Rejection::OutOfSequence {
+                last: 0,
+                received: 0
+            })
+        );
+        assert_eq!(
+            r.recv().unwrap(),
+            Command::Rejected(Rejection::Malformed("truncated frame"))
+        );
+        assert_eq!(r.recv().unwrap(), Command::Abort);
+
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn receives_tcp_commands() {
+        let generator = NetworkGenerator::tcp("127.0.0.1:0", Decoder).unwrap();
+        let address = generator.local_addr().unwrap();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        let mut client = CommandClient::tcp(address).unwrap();
+        // Frames split across and combined into TCP segments
+        let session = client.session();
+        let ping = encode_frame(session, 0, b"PING");
+        client.send_raw(&ping[..3]).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        client.send_raw(&ping[3..]).unwrap();
+        let mut both = encode_frame(session, 1, b"ARM");
+        both.extend(encode_frame(session, 1, b"ARM"));
+        client.send_raw(&both).unwrap();
+
+        assert_eq!(r.recv().unwrap(), Command::Ping);
+        assert_eq!(r.recv().unwrap(), Command::Arm);
+        assert_eq!(
+            r.recv().unwrap(),
+            Command::Rejected(Rejection::OutOfSequence {
+                last: 1,
+                received: 1
+            })
+        );
+
+        // A reconnecting ground station starts a new sequence
+        drop(client);
+        let mut client = CommandClient::tcp(address).unwrap();
+        client.send(b"ABORT").unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Abort);
+
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn restarted_udp_client_starts_new_sequence() {
+        let generator = NetworkGenerator::udp("127.0.0.1:0", Decoder).unwrap();
+        let address = generator.local_addr().unwrap();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        let mut client = CommandClient::udp(address).unwrap();
+        client.send(b"ARM").unwrap();
+        client.send(b"PING").unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Arm);
+        assert_eq!(r.recv().unwrap(), Command::Ping);
+
+        // The restarted ground station starts at sequence 0 again
+        let mut restarted = CommandClient::udp(address).unwrap();
+        assert_ne!(restarted.session(), client.session());
+        restarted.send(b"ABORT").unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Abort);
+        restarted.send(b"PING").unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Ping);
+
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn rejects_frames_of_superseded_sessions() {
+        let generator = NetworkGenerator::udp("127.0.0.1:0", Decoder).unwrap();
+        let address = generator.local_addr().unwrap();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        let mut client = CommandClient::udp(address).unwrap();
+        client.send_raw(&encode_frame(5, 0, b"ARM")).unwrap();
+        client.send_raw(&encode_frame(6, 0, b"PING")).unwrap();
+        // A delayed frame of the earlier session
+        client.send_raw(&encode_frame(5, 1, b"ABORT")).unwrap();
+        // Session ids wrap around like sequence numbers
+        client.send_raw(&encode_frame(4, 7, b"ABORT")).unwrap();
+        client.send_raw(&encode_frame(6, 1, b"ABORT")).unwrap();
+
+        assert_eq!(r.recv().unwrap(), Command::Arm);
+        assert_eq!(r.recv().unwrap(), Command::Ping);
+        for received in [5, 4] {
+            assert_eq!(
+                r.recv().unwrap(),
+                Command::Rejected(Rejection::StaleSession {
+                    current: 6,
+                    received
+                })
+            );
+        }
+        assert_eq!(r.recv().unwrap(), Command::Abort);
+
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn new_sessions_are_newer() {
+        let sessions: Vec<u32> = (0..100).map(|_| new_session()).collect();
+        for pair in sessions.windows(2) {
+            assert!(is_after(pair[1], pair[0]));
+        }
+    }
+
+    #[test]
+    fn sequence_wraps_around() {
+        assert!(is_after(1, 0));
+        assert!(is_after(0, u32::MAX));
+        assert!(is_after(5, u32::MAX - 5));
+        assert!(!is_after(u32::MAX, 0));
+        assert!(!is_after(7, 7));
+
+        let generator = NetworkGenerator::udp("127.0.0.1:0", Decoder).unwrap();
+        let address = generator.local_addr().unwrap();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        let mut client = CommandClient::udp(address).unwrap();
+        let session = client.session();
+        client
+            .send_raw(&encode_frame(session, u32::MAX, b"ARM"))
+            .unwrap();
+        client
+            .send_raw(&encode_frame(session, 0, b"ABORT"))
+            .unwrap();
+        client
+            .send_raw(&encode_frame(session, u32::MAX, b"PING"))
+            .unwrap();
+        assert_eq!(r.recv().unwrap(), Command::Arm);
+        assert_eq!(r.recv().unwrap(), Command::Abort);
+        assert_eq!(
+            r.recv().unwrap(),
+            Command::Rejected(Rejection::OutOfSequence {
+                last: 0,
+                received: u32::MAX
+            })
+        );
+
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn stops_on_dropped_receiver() {
+        let generator = NetworkGenerator::udp("127.0.0.1:0", Decoder).unwrap();
+        let address = generator.local_addr().unwrap();
+        let (s, r) = mpsc::channel();
+        let handle = generator.start(s).unwrap();
+        drop(r);
+
+        CommandClient::udp(address).unwrap().send(b"PING").unwrap();
+        while !handle.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -1,40 +1,64 @@
 use crate::event_generator::EventGenerator;
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::CountingSender;
 
+use std::convert::Infallible;
 use std::marker::Send;
 use std::sync::mpsc::Sender;
-use std::thread;
+use std::time::Instant;
 
 pub struct OneShotGenerator<T: Send> {
     pub value: T,
 }
 
-impl<T: 'static + std::marker::Send> EventGenerator<T, ()> for OneShotGenerator<T> {
-    type Handle = ();
-    fn start(self, send_handle: Sender<T>) -> Self::Handle {
-        thread::spawn(move || {
-            send_handle.send(self.value).unwrap();
+pub type OneShotGenHandle = TimerHandle;
+
+impl<T: 'static + Send> OneShotGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> OneShotGenHandle {
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let mut value = Some(self.value);
+        let timer = scheduler.schedule(Instant::now(), move |_now| {
+            if let Some(value) = value.take() {
+                // A disconnected receiver means nobody is interested in the event anymore,
+                // which is not an error for a generator.
+                let _ = send_handle.send(value);
+            }
+            None
         });
+        timer.with_stats(recorder)
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for OneShotGenerator<T> {
+    type Handle = OneShotGenHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::event_generator::EventGenHandle;
     use std::sync::mpsc;
 
     #[test]
     fn generates_one_event() {
         let (s, r) = mpsc::channel();
         let one_shot = OneShotGenerator { value: 42 };
-        one_shot.start(s);
+        let handle = one_shot.start(s).unwrap();
         assert_eq!(r.recv().unwrap(), 42);
+        assert_eq!(handle.stats().events_sent, 1);
     }
 
     #[test]
     fn generates_only_one_event() {
         let (s, r) = mpsc::channel();
         let one_shot = OneShotGenerator { value: 42 };
-        one_shot.start(s);
+        one_shot.start(s).unwrap();
         assert_eq!(r.recv().unwrap(), 42);
 
         assert!(
@@ -42,4 +66,14 @@ mod tests {
             "The one shot event generator produced more than one message on the channel"
         );
     }
+
+    #[test]
+    fn survives_dropped_receiver() {
+        let (s, r) = mpsc::channel();
+        drop(r);
+        let one_shot = OneShotGenerator { value: 42 };
+        let mut handle = one_shot.start(s).unwrap();
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+    }
 }
@@ -0,0 +1,118 @@
+//! Emits randomly chosen events at random intervals to stress state implementations with
+//! unexpected event orderings. The same seed always produces the same sequence of events.
+
+use crate::event_generator::EventGenerator;
+use crate::rng::SeededRng;
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::CountingSender;
+
+use std::convert::Infallible;
+use std::marker::Send;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+pub struct RandomGenerator<T: Clone + Send> {
+    /// Events to choose from, each with its relative weight.
+    pub events: Vec<(T, u32)>,
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    pub seed: u64,
+}
+
+pub type RandomGenHandle = TimerHandle;
+
+impl<T: 'static + Clone + Send> RandomGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> RandomGenHandle {
+        let mut rng = SeededRng::new(self.seed);
+        let total_weight: u64 = self.events.iter().map(|(_, w)| u64::from(*w)).sum();
+        let (min, max) = (self.min_interval, self.max_interval);
+        let events = self.events;
+
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+
+        let first = Instant::now() + rng.duration_between(min, max);
+        let timer = scheduler.schedule(first, move |now| {
+            if total_weight == 0 {
+                return None;
+            }
+            let mut pick = rng.below(total_weight);
+            let (event, _) = events
+                .iter()
+                .find(|(_, weight)| match pick.checked_sub(u64::from(*weight)) {
+                    Some(rest) => {
+                        pick = rest;
+                        false
+                    }
+                    None => true,
+                })
+                .expect("pick is below the total weight");
+
+            if send_handle.send(event.clone()).is_err() {
+                return None;
+            }
+            Some(now + rng.duration_between(min, max))
+        });
+        timer.with_stats(recorder)
+    }
+}
+
+impl<T: 'static + Clone + Send> EventGenerator<T> for RandomGenerator<T> {
+    type Handle = RandomGenHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_generator::EventGenHandle;
+    use std::sync::mpsc;
+
+    fn generate(seed: u64, count: usize) -> Vec<char> {
+        let (s, r) = mpsc::channel();
+        let mut handle = RandomGenerator {
+            events: vec![('a', 1), ('b', 3), ('c', 0)],
+            min_interval: Duration::ZERO,
+            max_interval: Duration::from_micros(100),
+            seed,
+        }
+        .start(s)
+        .unwrap();
+        let events = r.iter().take(count).collect();
+        assert!(handle.stop().is_ok());
+        events
+    }
+
+    #[test]
+    fn is_reproducible() {
+        assert_eq!(generate(42, 50), generate(42, 50));
+        assert_ne!(generate(42, 50), generate(43, 50));
+    }
+
+    #[test]
+    fn respects_weights() {
+        let events = generate(1, 400);
+        let a = events.iter().filter(|e| **e == 'a').count();
+        let b = events.iter().filter(|e| **e == 'b').count();
+        assert!(!events.contains(&'c'));
+        assert!(a > 50 && b > 2 * a, "a: {}, b: {}", a, b);
+    }
+
+    #[test]
+    fn stops_without_events() {
+        let (s, r) = mpsc::channel::<char>();
+        RandomGenerator {
+            events: Vec::new(),
+            min_interval: Duration::ZERO,
+            max_interval: Duration::ZERO,
+            seed: 0,
+        }
+        .start(s)
+        .unwrap();
+        assert!(r.recv().is_err());
+    }
+}
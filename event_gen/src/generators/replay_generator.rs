@@ -0,0 +1,717 @@
+//! Replays recorded logs into a state machine for hardware free testing.
+//!
+//! Three log formats are supported:
+//! - [`LogFormat::Csv`]: one record per line, the first column being the timestamp in seconds
+//!   followed by the numeric values of the record, e.g. `0.125,1013.2,0.98`. Empty lines and
+//!   lines starting with `#` are ignored, as is a header line whose first column is not a number.
+//! - [`LogFormat::Binary`]: the [`BINARY_MAGIC`] header followed by records consisting of the
+//!   timestamp in microseconds (`u64`), the number of values (`u16`) and the values themselves
+//!   (`f64`), all little endian. Such logs can be written with [`BinaryLogWriter`].
+//! - [`LogFormat::FlightLog`]: a file written by the onboard flight data logger
+//!   ([`aurora_hal::logger`], starting with [`aurora_hal::logger::MAGIC`]). Each record holds the
+//!   values of all variables of the I/O tree in the order of the log's schema, booleans are
+//!   replayed as 0 and 1 and strings as NaN. Replay stops at a record damaged by a power loss.
+//!
+//! The binary replay format and the flight log format have different magics, so a file of one
+//! format is never mistaken for the other.
+//!
+//! The log is read ahead by a thread of its own, so reading never stalls the shared
+//! [`Scheduler`] which replays the records at their time.
+
+use crate::event_generator::EventGenerator;
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::CountingSender;
+
+use aurora_hal::logger::{LogError, LogReader, MAGIC as FLIGHT_LOG_MAGIC};
+use aurora_hal::Value;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::Send;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Header at the start of every binary replay log, the last byte is the format version.
+pub const BINARY_MAGIC: [u8; 8] = *b"AURRPL\x00\x01";
+
+/// A single timestamped entry of a log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Time of the record relative to the start of the recording.
+    pub timestamp: Duration,
+    pub values: Vec<f64>,
+}
+
+/// What the replay generator passes to its event mapper.
+#[derive(Debug)]
+pub enum ReplayItem {
+    Record(Record),
+    /// The end of the log has been reached, no more items follow.
+    EndOfFile,
+    /// The log could not be read or contains a malformed record, no more items follow.
+    Error(ReplayError),
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// Record number `record` (starting at 1) could not be parsed.
+    Malformed {
+        record: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "failed to read log: {e}"),
+            ReplayError::Malformed { record, reason } => {
+                write!(f, "malformed record {record}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<LogError> for ReplayError {
+    fn from(e: LogError) -> Self {
+        match e {
+            LogError::Io(e) => ReplayError::Io(e),
+            e => ReplayError::Malformed {
+                record: 0,
+                reason: e.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Csv,
+    Binary,
+    FlightLog,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplaySpeed {
+    /// Keep the time between records as recorded.
+    Original,
+    /// Divide the time between records by the given factor, i.e. `Scaled(2.0)` replays twice as
+    /// fast. Factors that are not positive replay as fast as possible.
+    Scaled(f64),
+    AsFastAsPossible,
+}
+
+pub struct ReplayGenerator<T: Send> {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub speed: ReplaySpeed,
+    /// Maps replayed items to events, items mapped to `None` are skipped.
+    pub event_mapper: fn(ReplayItem) -> Option<T>,
+}
+
+pub type ReplayGenHandle = TimerHandle;
+
+/// Records read ahead of the replay.
+const READ_AHEAD: usize = 64;
+/// How long the replay waits for the reader before checking again. The reader wakes the replay
+/// as soon as it has read a record, this only guards against a missed wakeup.
+const READ_WAIT: Duration = Duration::from_millis(100);
+
+type Records = Box<dyn Iterator<Item = Result<Record, ReplayError>> + Send>;
+
+impl<T: 'static + Send> ReplayGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    ///
+    /// Fails if the log cannot be opened or, for binary and flight logs, does not start with the
+    /// header of its format.
+    pub fn start_with(
+        self,
+        scheduler: &Scheduler,
+        send_handle: Sender<T>,
+    ) -> Result<ReplayGenHandle, ReplayError> {
+        let records = open_log(&self.path, self.format)?;
+        let (record_sender, record_receiver) =
+            mpsc::sync_channel::<Result<Record, ReplayError>>(READ_AHEAD);
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let mapper = self.event_mapper;
+        // Returns false once the receiver is gone, which ends the replay.
+        let emit = move |item| match mapper(item) {
+            Some(event) => send_handle.send(event).is_ok(),
+            None => true,
+        };
+
+        let speed = self.speed;
+        let mut anchor: Option<(Instant, Duration)> = None;
+        let mut pending: Option<(Record, Instant)> = None;
+
+        let timer = scheduler.schedule(Instant::now(), move |now| {
+            if let Some((record, deadline)) = pending.take() {
+                // Woken early by the reader
+                if deadline > now {
+                    pending = Some((record, deadline));
+                    return Some(deadline);
+                }
+                if !emit(ReplayItem::Record(record)) {
+                    return None;
+                }
+            }
+
+            match record_receiver.try_recv() {
+                Ok(Ok(record)) => {
+                    let (start, first) = *anchor.get_or_insert((now, record.timestamp));
+                    let offset = record.timestamp.saturating_sub(first);
+                    let deadline = match speed {
+                        ReplaySpeed::Original => start + offset,
+                        ReplaySpeed::Scaled(factor) if factor > 0.0 && factor.is_finite() => {
+                            start + offset.div_f64(factor)
+                        }
+                        ReplaySpeed::Scaled(_) | ReplaySpeed::AsFastAsPossible => now,
+                    };
+                    pending = Some((record, deadline));
+                    Some(deadline)
+                }
+                Ok(Err(e)) => {
+                    emit(ReplayItem::Error(e));
+                    None
+                }
+                // The reader wakes the replay once the next record is read.
+                Err(TryRecvError::Empty) => Some(now + READ_WAIT),
+                Err(TryRecvError::Disconnected) => {
+                    emit(ReplayItem::EndOfFile);
+                    None
+                }
+            }
+        });
+
+        // Ends after the last record or once the replay is stopped and drops the receiver.
+        let waker = timer.waker();
+        thread::spawn(move || {
+            for record in records {
+                let failed = record.is_err();
+                if record_sender.send(record).is_err() {
+                    return;
+                }
+                waker.wake_at(Instant::now());
+                if failed {
+                    return;
+                }
+            }
+            // Dropping the sender ends the replay
+            drop(record_sender);
+            waker.wake_at(Instant::now());
+        });
+        Ok(timer.with_stats(recorder))
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for ReplayGenerator<T> {
+    type Handle = ReplayGenHandle;
+    type Error = ReplayError;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        self.start_with(Scheduler::global(), send_handle)
+    }
+}
+
+fn open_log(path: &PathBuf, format: LogFormat) -> Result<Records, ReplayError> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(match format {
+        LogFormat::Csv => Box::new(CsvRecords::new(reader)),
+        LogFormat::Binary => Box::new(BinaryRecords::new(reader)?),
+        LogFormat::FlightLog => Box::new(FlightLogRecords::new(LogReader::open(path)?)),
+    })
+}
+
+/// Iterator over the records of a CSV log.
+pub struct CsvRecords<R: BufRead> {
+    lines: io::Lines<R>,
+    record: usize,
+}
+
+impl<R: BufRead> CsvRecords<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            record: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvRecords<R> {
+    type Item = Result<Record, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut columns = line.split(',').map(str::trim);
+            let seconds = columns.next().unwrap_or_default().parse::<f64>();
+            if seconds.is_err() && self.record == 0 {
+                // Header line
+                continue;
+            }
+            self.record += 1;
+
+            let Ok(seconds) = seconds else {
+                return Some(Err(self.malformed("timestamp is not a number")));
+            };
+            let Ok(timestamp) = Duration::try_from_secs_f64(seconds) else {
+                return Some(Err(self.malformed("timestamp is negative or too large")));
+            };
+            let values: Result<Vec<f64>, _> = columns.map(str::parse::<f64>).collect();
+            return Some(match values {
+                Ok(values) => Ok(Record { timestamp, values }),
+                Err(e) => Err(self.malformed(&format!("invalid value: {e}"))),
+            });
+        }
+    }
+}
+
+impl<R: BufRead> CsvRecords<R> {
+    fn malformed(&self, reason: &str) -> ReplayError {
+        ReplayError::Malformed {
+            record: self.record,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Iterator over the records of a binary log.
+pub struct BinaryRecords<R: Read> {
+    reader: R,
+    record: usize,
+}
+
+impl<R: Read> BinaryRecords<R> {
+    /// Reads and checks the header of the log.
+    pub fn new(mut reader: R) -> Result<Self, ReplayError> {
+        let mut magic = [0; BINARY_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != BINARY_MAGIC {
+            let reason = if magic.starts_with(FLIGHT_LOG_MAGIC) {
+                "flight logs are replayed with LogFormat::FlightLog"
+            } else {
+                "missing binary log header"
+            };
+            return Err(ReplayError::Malformed {
+                record: 0,
+                reason: reason.to_string(),
+            });
+        }
+        Ok(Self { reader, record: 0 })
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, ReplayError> {
+        let mut micros = [0; 8];
+        // A clean end of the log is only allowed between records.
+        match self.reader.read(&mut micros[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut micros[1..])?,
+        }
+        let mut count = [0; 2];
+        self.reader.read_exact(&mut count)?;
+
+        let mut values = Vec::with_capacity(u16::from_le_bytes(count).into());
+        for _ in 0..u16::from_le_bytes(count) {
+            let mut value = [0; 8];
+            self.reader.read_exact(&mut value)?;
+            values.push(f64::from_le_bytes(value));
+        }
+        Ok(Some(Record {
+            timestamp: Duration::from_micros(u64::from_le_bytes(micros)),
+            values,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for BinaryRecords<R> {
+    type Item = Result<Record, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.record += 1;
+        match self.read_record() {
+            Ok(record) => record.map(Ok),
+            Err(ReplayError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Some(Err(ReplayError::Malformed {
+                    record: self.record,
+                    reason: "log ends in the middle of a record".to_string(),
+                }))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Iterator over the records of a flight log written by [`aurora_hal::logger::Logger`].
+pub struct FlightLogRecords {
+    reader: LogReader,
+    record: usize,
+    done: bool,
+}
+
+impl FlightLogRecords {
+    pub fn new(reader: LogReader) -> Self {
+        Self {
+            reader,
+            record: 0,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for FlightLogRecords {
+    type Item = Result<Record, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        self.record += 1;
+        let Some(record) = self.reader.next_record() else {
+            self.done = true;
+            return self.reader.is_torn().then(|| {
+                Err(ReplayError::Malformed {
+                    record: self.record,
+                    reason: "record is damaged".to_string(),
+                })
+            });
+        };
+        Some(Ok(Record {
+            timestamp: record.timestamp,
+            values: record.values.iter().map(replay_value).collect(),
+        }))
+    }
+}
+
+fn replay_value(value: &Value) -> f64 {
+    match *value {
+        Value::U64(v) => v as f64,
+        Value::U32(v) => v.into(),
+        Value::U16(v) => v.into(),
+        Value::I64(v) => v as f64,
+        Value::I32(v) => v.into(),
+        Value::I16(v) => v.into(),
+        Value::F64(v) => v,
+        Value::F32(v) => v.into(),
+        Value::Bool(v) => f64::from(u8::from(v)),
+        Value::Str(_) => f64::NAN,
+    }
+}
+
+/// Writes records in the binary log format read by [`LogFormat::Binary`].
+pub struct BinaryLogWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BinaryLogWriter<W> {
+    /// Writes the log header and returns the writer.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&BINARY_MAGIC)?;
+        Ok(Self { writer })
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the record has more than `u16::MAX` values.
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let count = u16::try_from(record.values.len()).expect("Too many values in one record");
+        let micros = u64::try_from(record.timestamp.as_micros()).unwrap_or(u64::MAX);
+        self.writer.write_all(&micros.to_le_bytes())?;
+        self.writer.write_all(&count.to_le_bytes())?;
+        for value in &record.values {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    mod tree {
+        aurora_hal::init_io_tree!("tests/config/IoTree.toml", "tests/config/Callbacks.toml");
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Value(f64),
+        Eof,
+        Error,
+    }
+
+    fn map(item: ReplayItem) -> Option<Event> {
+        match item {
+            ReplayItem::Record(r) => Some(Event::Value(r.values[0])),
+            ReplayItem::EndOfFile => Some(Event::Eof),
+            ReplayItem::Error(_) => Some(Event::Error),
+        }
+    }
+
+    fn temp_log(name: &str, contents: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("event_gen_replay_{}_{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_csv() {
+        let csv = "time,pressure\n# comment\n0.0,1.5\n\n0.25, 2.5\n";
+        let records: Vec<Record> = CsvRecords::new(csv.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    timestamp: Duration::ZERO,
+                    values: vec![1.5]
+                },
+                Record {
+                    timestamp: Duration::from_millis(250),
+                    values: vec![2.5]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_csv() {
+        let csv = "0.0,1.0\n0.1,abc\n";
+        let mut records = CsvRecords::new(csv.as_bytes());
+        assert!(records.next().unwrap().is_ok());
+        assert!(matches!(
+            records.next(),
+            Some(Err(ReplayError::Malformed { record: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let records = vec![
+            Record {
+                timestamp: Duration::from_micros(10),
+                values: vec![1.0, -2.0],
+            },
+            Record {
+                timestamp: Duration::from_micros(20),
+                values: vec![],
+            },
+        ];
+        let mut writer = BinaryLogWriter::new(Vec::new()).unwrap();
+        for record in &records {
+            writer.write_record(record).unwrap();
+        }
+        let bytes = writer.into_inner();
+
+        let read: Vec<Record> = BinaryRecords::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+
+        let mut truncated = BinaryRecords::new(&bytes[..bytes.len() - 3]).unwrap();
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(matches!(
+            truncated.next(),
+            Some(Err(ReplayError::Malformed { record: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn replays_csv_then_eof() {
+        let path = temp_log("eof.csv", b"0.0,1\n0.001,2\n0.002,3\n");
+        let (s, r) = mpsc::channel();
+        ReplayGenerator {
+            path: path.clone(),
+            format: LogFormat::Csv,
+            speed: ReplaySpeed::AsFastAsPossible,
+            event_mapper: map,
+        }
+        .start(s)
+        .unwrap();
+
+        let events: Vec<Event> = r.iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Value(1.0),
+                Event::Value(2.0),
+                Event::Value(3.0),
+                Event::Eof
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keeps_original_timing() {
+        let path = temp_log("timing.csv", b"1.0,1\n1.1,2\n");
+        let (s, r) = mpsc::channel();
+        ReplayGenerator {
+            path: path.clone(),
+            format: LogFormat::Csv,
+            speed: ReplaySpeed::Original,
+            event_mapper: map,
+        }
+        .start(s)
+        .unwrap();
+
+        assert_eq!(r.recv().unwrap(), Event::Value(1.0));
+        let first = Instant::now();
+        assert_eq!(r.recv().unwrap(), Event::Value(2.0));
+        let elapsed = first.elapsed();
+        assert!(
+            elapsed > Duration::from_millis(80) && elapsed < Duration::from_millis(150),
+            "elapsed: {:?}",
+            elapsed
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn scales_timing() {
+        let mut writer = BinaryLogWriter::new(Vec::new()).unwrap();
+        for (i, t) in [0, 200].iter().enumerate() {
+            writer
+                .write_record(&Record {
+                    timestamp: Duration::from_millis(*t),
+                    values: vec![i as f64],
+                })
+                .unwrap();
+        }
+        let path = temp_log("scaled.bin", &writer.into_inner());
+        let (s, r) = mpsc::channel();
+        ReplayGenerator {
+            path: path.clone(),
+            format: LogFormat::Binary,
+            speed: ReplaySpeed::Scaled(4.0),
+            event_mapper: map,
+        }
+        .start(s)
+        .unwrap();
+
+        assert_eq!(r.recv().unwrap(), Event::Value(0.0));
+        let first = Instant::now();
+        assert_eq!(r.recv().unwrap(), Event::Value(1.0));
+        let elapsed = first.elapsed();
+        assert!(
+            elapsed > Duration::from_millis(30) && elapsed < Duration::from_millis(100),
+            "elapsed: {:?}",
+            elapsed
+        );
+        assert_eq!(r.recv().unwrap(), Event::Eof);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replays_flight_log() {
+        use aurora_hal::logger::{Logger, LoggerConfig};
+        use aurora_hal::PathRegistry;
+        use tree::IOTREE;
+
+        let config = LoggerConfig {
+            directory: std::env::temp_dir()
+                .join(format!("event_gen_replay_{}_flight", std::process::id())),
+            ..LoggerConfig::default()
+        };
+        let _ = std::fs::remove_dir_all(&config.directory);
+        {
+            let mut logger = Logger::create(config.clone(), &*IOTREE).unwrap();
+            IOTREE
+                .set_by_path("process.Sensor1.pressure", Value::U32(1013))
+                .unwrap();
+            logger.record(&*IOTREE).unwrap();
+            IOTREE
+                .set_by_path("process.Sensor1.pressure", Value::U32(990))
+                .unwrap();
+            logger.record(&*IOTREE).unwrap();
+        }
+        let path = config.file_path(0);
+        let schema = LogReader::open(&path).unwrap().schema().clone();
+        let pressure = schema
+            .fields()
+            .iter()
+            .position(|field| field.path == "process.Sensor1.pressure")
+            .unwrap();
+        let state = schema
+            .fields()
+            .iter()
+            .position(|field| field.path == "control.state")
+            .unwrap();
+
+        let (s, r) = mpsc::channel();
+        ReplayGenerator {
+            path: path.clone(),
+            format: LogFormat::FlightLog,
+            speed: ReplaySpeed::AsFastAsPossible,
+            event_mapper: |item| match item {
+                ReplayItem::Record(record) => Some(Ok(record.values)),
+                ReplayItem::EndOfFile => None,
+                ReplayItem::Error(e) => Some(Err(e.to_string())),
+            },
+        }
+        .start(s)
+        .unwrap();
+
+        let records: Vec<Vec<f64>> = r.iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].len(), schema.fields().len());
+        assert_eq!(records[0][pressure], 1013.0);
+        assert_eq!(records[1][pressure], 990.0);
+        assert!(records[0][state].is_nan());
+
+        // Not mistaken for the binary replay format
+        let binary = ReplayGenerator {
+            path,
+            format: LogFormat::Binary,
+            speed: ReplaySpeed::AsFastAsPossible,
+            event_mapper: map,
+        }
+        .start(mpsc::channel().0);
+        assert!(matches!(
+            binary,
+            Err(ReplayError::Malformed { record: 0, .. })
+        ));
+        std::fs::remove_dir_all(config.directory).unwrap();
+    }
+
+    #[test]
+    fn fails_to_start_without_log() {
+        let (s, r) = mpsc::channel();
+        let result = ReplayGenerator {
+            path: PathBuf::from("/nonexistent/event_gen/replay.csv"),
+            format: LogFormat::Csv,
+            speed: ReplaySpeed::Original,
+            event_mapper: map,
+        }
+        .start(s);
+
+        assert!(matches!(result, Err(ReplayError::Io(_))));
+        assert!(r.recv().is_err());
+    }
+}
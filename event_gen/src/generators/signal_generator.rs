@@ -0,0 +1,220 @@
+//! Turns Unix signals, e.g. Ctrl-C (`SIGINT`) or `SIGTERM`, into events.
+//!
+//! While a [`SignalGenerator`] handles a signal, its default action (usually terminating the
+//! process) does not happen and the state machine is expected to react to the event instead, e.g.
+//! by moving into a safe state and exiting. Once no generator handles the signal anymore, because
+//! they have been stopped or dropped, the default action happens again.
+
+use crate::event_generator::{EventGenHandle, EventGenerator};
+use crate::stats::{CountingSender, GenStats, StatsRecorder};
+
+use signal_hook::consts::FORBIDDEN;
+use signal_hook::flag;
+use signal_hook::iterator::{Handle, Signals};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::io;
+use std::marker::Send;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2};
+
+pub struct SignalGenerator<T: Send> {
+    signals: Signals,
+    handled: Handled,
+    event_producer: fn(i32) -> T,
+}
+
+/// Emulates the default action of a signal while no generator handles it.
+///
+/// signal-hook cannot restore the default action once its handler is installed, removing the
+/// generator's handler would leave the signal ignored. The emulation is registered once per
+/// signal and only enabled while the signal is unhandled.
+struct DefaultAction {
+    unhandled: Arc<AtomicBool>,
+    generators: usize,
+}
+
+static DEFAULT_ACTIONS: Mutex<BTreeMap<i32, DefaultAction>> = Mutex::new(BTreeMap::new());
+
+/// Counts a generator as handling its signals until dropped.
+struct Handled(Vec<i32>);
+
+impl Handled {
+    fn new(signals: &[i32]) -> io::Result<Self> {
+        let mut actions = DEFAULT_ACTIONS.lock().unwrap();
+        for (i, signal) in signals.iter().enumerate() {
+            if !actions.contains_key(signal) {
+                let unhandled = Arc::new(AtomicBool::new(true));
+                if let Err(e) = flag::register_conditional_default(*signal, unhandled.clone()) {
+                    release(&mut actions, &signals[..i]);
+                    return Err(e);
+                }
+                actions.insert(
+                    *signal,
+                    DefaultAction {
+                        unhandled,
+                        generators: 0,
+                    },
+                );
+            }
+            let action = actions.get_mut(signal).unwrap();
+            action.generators += 1;
+            action.unhandled.store(false, Ordering::SeqCst);
+        }
+        Ok(Self(signals.to_vec()))
+    }
+}
+
+impl Drop for Handled {
+    fn drop(&mut self) {
+        release(&mut DEFAULT_ACTIONS.lock().unwrap(), &self.0);
+    }
+}
+
+fn release(actions: &mut BTreeMap<i32, DefaultAction>, signals: &[i32]) {
+    for signal in signals {
+        if let Some(action) = actions.get_mut(signal) {
+            action.generators -= 1;
+            if action.generators == 0 {
+                action.unhandled.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl<T: Send> SignalGenerator<T> {
+    /// Registers handlers for `signals`, each received signal is passed to `event_producer`.
+    ///
+    /// Fails if one of the signals cannot be handled, e.g. `SIGKILL`, or has no known default
+    /// action.
+    pub fn new(signals: &[i32], event_producer: fn(i32) -> T) -> io::Result<Self> {
+        // signal_hook panics when asked to register these.
+        if let Some(signal) = signals.iter().find(|s| FORBIDDEN.contains(s)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("signal {signal} cannot be handled"),
+            ));
+        }
+        Ok(Self {
+            handled: Handled::new(signals)?,
+            signals: Signals::new(signals)?,
+            event_producer,
+        })
+    }
+}
+
+pub struct SignalGenHandle {
+    join_handle: Option<thread::JoinHandle<()>>,
+    signals: Handle,
+    stats: Arc<StatsRecorder>,
+}
+
+impl EventGenHandle for SignalGenHandle {
+    fn stop(&mut self) -> thread::Result<()> {
+        self.signals.close();
+        match self.join_handle.take() {
+            Some(join_handle) => join_handle.join(),
+            None => Ok(()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_none_or(thread::JoinHandle::is_finished)
+    }
+
+    fn stats(&self) -> GenStats {
+        self.stats.snapshot()
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for SignalGenerator<T> {
+    type Handle = SignalGenHandle;
+    type Error = Infallible;
+    fn start(mut self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        let signals = self.signals.handle();
+        let send_handle = CountingSender::new(send_handle);
+        let stats = send_handle.recorder();
+        let join_handle = thread::spawn(move || {
+            // Ends once the handle closes the signal iterator.
+            for signal in self.signals.forever() {
+                if send_handle.send((self.event_producer)(signal)).is_err() {
+                    break;
+                }
+            }
+            // The signals are unhandled from now on
+            drop(self.handled);
+        });
+
+        Ok(Self::Handle {
+            join_handle: Some(join_handle),
+            signals,
+            stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn generates_event_on_signal() {
+        let (s, r) = mpsc::channel();
+        let generator = SignalGenerator::new(&[SIGUSR1], |signal| signal).unwrap();
+        let mut handle = generator.start(s).unwrap();
+
+        signal_hook::low_level::raise(SIGUSR1).unwrap();
+        assert_eq!(r.recv_timeout(Duration::from_secs(1)).unwrap(), SIGUSR1);
+        assert_eq!(handle.stats().events_sent, 1);
+
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+        assert!(r.recv().is_err());
+    }
+
+    #[test]
+    fn restores_default_action_after_stop() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::{Command, Stdio};
+
+        // SIGUSR2 terminates the process by default, so this runs in a child process.
+        const CHILD: &str = "EVENT_GEN_SIGNAL_CHILD";
+        if std::env::var_os(CHILD).is_some() {
+            let (s, _r) = mpsc::channel();
+            let mut handle = SignalGenerator::new(&[SIGUSR2], |signal| signal)
+                .unwrap()
+                .start(s)
+                .unwrap();
+            handle.stop().unwrap();
+            signal_hook::low_level::raise(SIGUSR2).unwrap();
+            thread::sleep(Duration::from_secs(1));
+            return;
+        }
+
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "generators::signal_generator::tests::restores_default_action_after_stop",
+            ])
+            .env(CHILD, "1")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert_eq!(status.signal(), Some(SIGUSR2));
+    }
+
+    #[test]
+    fn rejects_forbidden_signals() {
+        let kill = signal_hook::consts::SIGKILL;
+        assert!(SignalGenerator::new(&[kill], |signal| signal).is_err());
+    }
+}
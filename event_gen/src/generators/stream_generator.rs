@@ -0,0 +1,215 @@
+//! Turns frames read from a byte stream, e.g. a radio modem or GPS receiver on a serial port,
+//! into events.
+//!
+//! The stream is read on a dedicated thread until it ends or the generator is stopped. Since a
+//! blocking read cannot be interrupted, serial ports should be opened with a read timeout so that
+//! the generator notices when it is stopped; timed out reads are simply retried.
+
+use crate::event_generator::{EventGenerator, ThreadGenHandle};
+use crate::framing::{FrameDecoder, Framer, Rejection};
+use crate::stats::CountingSender;
+
+use std::convert::Infallible;
+use std::io::{self, Read};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+
+pub struct StreamGenerator<R: Read + Send + 'static, F: Framer, D: FrameDecoder> {
+    pub reader: R,
+    pub framer: F,
+    pub decoder: D,
+}
+
+impl<R, F, D> EventGenerator<D::Event> for StreamGenerator<R, F, D>
+where
+    R: Read + Send + 'static,
+    F: Framer,
+    D: FrameDecoder,
+{
+    type Handle = ThreadGenHandle;
+    type Error = Infallible;
+    fn start(mut self, send_handle: Sender<D::Event>) -> Result<Self::Handle, Self::Error> {
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let handle = ThreadGenHandle::spawn(move |stop_flag| {
+            let mut buf = [0; 256];
+            let mut index: u32 = 0;
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                let len = match self.reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock
+                                | io::ErrorKind::TimedOut
+                                | io::ErrorKind::Interrupted
+                        ) =>
+                    {
+                        continue
+                    }
+                    // The device is gone, there is nothing left to generate events from.
+                    Err(_) => break,
+                };
+
+                for byte in &buf[..len] {
+                    let mut frame = self.framer.push(*byte);
+                    while let Some(result) = frame {
+                        let event = match result {
+                            Ok(payload) => {
+                                let sequence = index;
+                                index = index.wrapping_add(1);
+                                match self.decoder.decode(&payload) {
+                                    Some(event) => Some(event),
+                                    None => {
+                                        self.decoder.rejected(Rejection::Undecodable { sequence })
+                                    }
+                                }
+                            }
+                            Err(rejection) => {
+                                index = index.wrapping_add(1);
+                                self.decoder.rejected(rejection)
+                            }
+                        };
+                        if let Some(event) = event {
+                            if send_handle.send(event).is_err() {
+                                return;
+                            }
+                        }
+                        frame = self.framer.poll();
+                    }
+                }
+            }
+        });
+        Ok(handle.with_stats(recorder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_generator::EventGenHandle;
+    use crate::framing::{cobs_encode, length_prefixed_encode, LineFramer, SlipFramer};
+    use crate::framing::{slip_encode, CobsFramer, LengthPrefixedFramer};
+    use std::io::Cursor;
+    use std::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Frame(Vec<u8>),
+        Rejected(Rejection),
+    }
+
+    struct Decoder;
+
+    impl FrameDecoder for Decoder {
+        type Event = Event;
+
+        fn decode(&mut self, payload: &[u8]) -> Option<Event> {
+            if payload == b"garbage" {
+                None
+            } else {
+                Some(Event::Frame(payload.to_vec()))
+            }
+        }
+
+        fn rejected(&mut self, rejection: Rejection) -> Option<Event> {
+            Some(Event::Rejected(rejection))
+        }
+    }
+
+    fn run(bytes: Vec<u8>, framer: impl Framer) -> Vec<Event> {
+        let (s, r) = mpsc::channel();
+        let mut handle = StreamGenerator {
+            reader: Cursor::new(bytes),
+            framer,
+            decoder: Decoder,
+        }
+        .start(s)
+        .unwrap();
+        let events = r.iter().collect();
+        assert!(handle.stop().is_ok());
+        events
+    }
+
+    #[test]
+    fn reads_lines() {
+        assert_eq!(
+            run(b"arm\ngarbage\nping\n".to_vec(), LineFramer::new()),
+            vec![
+                Event::Frame(b"arm".to_vec()),
+                Event::Rejected(Rejection::Undecodable { sequence: 1 }),
+                Event::Frame(b"ping".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_cobs_frames() {
+        let mut bytes = cobs_encode(b"\x00\x01");
+        bytes.extend(b"\x05\x00");
+        bytes.extend(cobs_encode(b"\x02"));
+        assert_eq!(
+            run(bytes, CobsFramer::new()),
+            vec![
+                Event::Frame(b"\x00\x01".to_vec()),
+                Event::Rejected(Rejection::Malformed("invalid COBS encoding")),
+                Event::Frame(b"\x02".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_slip_frames() {
+        assert_eq!(
+            run(slip_encode(b"\xC0\xDB"), SlipFramer::new()),
+            vec![Event::Frame(b"\xC0\xDB".to_vec())]
+        );
+    }
+
+    #[test]
+    fn reports_crc_errors() {
+        let mut bytes = length_prefixed_encode(b"gps");
+        bytes[4] = b'x';
+        bytes.extend(length_prefixed_encode(b"gps"));
+        assert_eq!(
+            run(bytes, LengthPrefixedFramer::new()),
+            vec![
+                Event::Rejected(Rejection::Malformed("checksum mismatch")),
+                Event::Frame(b"gps".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_reading_endless_stream() {
+        let (s, _r) = mpsc::channel();
+        let mut handle = StreamGenerator {
+            reader: io::repeat(b'\n'),
+            framer: LineFramer::new(),
+            decoder: Decoder,
+        }
+        .start(s)
+        .unwrap();
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn stops_on_dropped_receiver() {
+        let (s, r) = mpsc::channel();
+        drop(r);
+        // The zero bytes lack the sync marker, which is reported.
+        let handle = StreamGenerator {
+            reader: io::repeat(0),
+            framer: LengthPrefixedFramer::new(),
+            decoder: Decoder,
+        }
+        .start(s)
+        .unwrap();
+        while !handle.is_finished() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+}
@@ -1,61 +1,66 @@
-use crate::event_generator::{EventGenHandle, EventGenerator};
+use crate::event_generator::EventGenerator;
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::CountingSender;
 
+use std::convert::Infallible;
 use std::marker::Send;
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
-use std::thread;
 use std::time::{Duration, Instant};
 
 pub struct TickGenerator<T: Send> {
     pub min_duration: Duration,
+    /// Called on the timer thread with the current and the previous tick, it should not block.
     pub event_producer: fn(Instant, Instant) -> T,
 }
 
-pub struct TickGenHandle {
-    join_handle: Option<thread::JoinHandle<()>>,
-    stop_flag: Arc<AtomicBool>,
-}
+pub type TickGenHandle = TimerHandle;
+
+impl<T: 'static + Send> TickGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> TickGenHandle {
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let task_recorder = recorder.clone();
+        let min_duration = self.min_duration;
+        let mut last_time = Instant::now();
+        let timer = scheduler.schedule(last_time + min_duration, move |now| {
+            // Running a whole interval late means at least one tick was missed.
+            let deadline = last_time + min_duration;
+            if min_duration > Duration::ZERO
+                && now.saturating_duration_since(deadline) >= min_duration
+            {
+                task_recorder.record_overrun();
+            }
 
-impl EventGenHandle for TickGenHandle {
-    fn stop(&mut self) {
-        self.stop_flag.store(true, Ordering::Relaxed);
-        if let Some(join_handle) = self.join_handle.take() {
-            join_handle
-                .join()
-                .expect("Failed to join thread of tick genenerator after sending exit signal");
-        }
+            // The receiver is dropped when the FSM leaves the state owning this generator,
+            // treat this as a regular shutdown.
+            if send_handle
+                .send((self.event_producer)(now, last_time))
+                .is_err()
+            {
+                return None;
+            }
+            last_time = Instant::now();
+            Some(last_time + min_duration)
+        });
+        timer.with_stats(recorder)
     }
 }
 
-impl<T: 'static + Send> EventGenerator<T, ()> for TickGenerator<T> {
+impl<T: 'static + Send> EventGenerator<T> for TickGenerator<T> {
     type Handle = TickGenHandle;
-    fn start(self, send_handle: Sender<T>) -> Self::Handle {
-        let stop_flag = Arc::new(AtomicBool::new(false));
-        let stop_flag_2 = stop_flag.clone();
-
-        let join_handle = thread::spawn(move || {
-            let mut last_time = Instant::now();
-            while !stop_flag_2.load(Ordering::Relaxed) {
-                thread::sleep(self.min_duration);
-                send_handle
-                    .send((self.event_producer)(Instant::now(), last_time))
-                    .unwrap();
-                last_time = Instant::now();
-            }
-        });
-
-        Self::Handle {
-            join_handle: Some(join_handle),
-            stop_flag,
-        }
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::event_generator::EventGenHandle;
     use std::sync::mpsc;
+    use std::thread;
 
     #[test]
     fn generates_at_least_20_events() {
@@ -65,7 +70,7 @@ mod tests {
             min_duration: Duration::from_millis(1),
             event_producer: |_now, _prev| 42,
         };
-        tick_gen.start(s);
+        tick_gen.start(s).unwrap();
 
         for _ in 0..20 {
             assert_eq!(r.recv().unwrap(), 42);
@@ -74,6 +79,8 @@ mod tests {
 
     #[test]
     fn is_not_too_fast() {
+        // A scheduler of its own, so other tests do not delay the ticks
+        let scheduler = Scheduler::new();
         let test_duration = Duration::from_millis(100);
         let (s, r) = mpsc::channel::<Duration>();
 
@@ -82,7 +89,7 @@ mod tests {
             event_producer: |now, prev| now - prev,
         };
 
-        tick_gen.start(s);
+        tick_gen.start_with(&scheduler, s);
 
         for _ in 0..4 {
             let iter_duration = r.recv().unwrap();
@@ -97,6 +104,7 @@ mod tests {
 
     #[test]
     fn is_not_too_slow() {
+        let scheduler = Scheduler::new();
         let test_duration = Duration::from_millis(100);
         let acceptable_error = Duration::from_millis(15);
         let (s, r) = mpsc::channel::<Duration>();
@@ -106,7 +114,7 @@ mod tests {
             event_producer: |now, prev| now - prev,
         };
 
-        tick_gen.start(s);
+        tick_gen.start_with(&scheduler, s);
 
         for _ in 0..4 {
             let iter_duration = r.recv().unwrap();
@@ -122,6 +130,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants, clippy::redundant_pattern_matching)]
     fn does_stop() {
         let (s, r) = mpsc::channel::<i32>();
 
@@ -130,8 +139,8 @@ mod tests {
             event_producer: |_now, _prev| 42,
         };
 
-        let mut handle = tick_gen.start(s);
-        handle.stop();
+        let mut handle = tick_gen.start(s).unwrap();
+        handle.stop().unwrap();
 
         let stop_time = Instant::now();
         while Instant::now() - stop_time < Duration::from_millis(500) {
@@ -148,4 +157,71 @@ mod tests {
 
         assert!(false, "Failed to stop within 500 ms");
     }
+
+    #[test]
+    fn stop_finishes_generator() {
+        let (s, _r) = mpsc::channel::<i32>();
+
+        let mut handle = TickGenerator {
+            min_duration: Duration::from_millis(1),
+            event_producer: |_now, _prev| 42,
+        }
+        .start(s)
+        .unwrap();
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn counts_events_and_overruns() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel::<i32>();
+        let mut handle = TickGenerator {
+            min_duration: Duration::from_millis(5),
+            event_producer: |_now, _prev| 42,
+        }
+        .start_with(&scheduler, s);
+
+        assert_eq!(r.recv().unwrap(), 42);
+        // Block the timer thread for a few intervals
+        let (blocked, unblock) = mpsc::channel::<()>();
+        scheduler.schedule(Instant::now(), move |_| {
+            let _ = unblock.recv_timeout(Duration::from_millis(30));
+            None
+        });
+        for _ in 0..3 {
+            assert_eq!(r.recv().unwrap(), 42);
+        }
+        drop(blocked);
+
+        let stats = handle.stats();
+        assert!(stats.events_sent >= 4);
+        assert!(stats.overruns >= 1, "{:?}", stats);
+        assert_eq!(stats.send_failures, 0);
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn stops_on_dropped_receiver() {
+        let (s, r) = mpsc::channel::<i32>();
+
+        let tick_gen = TickGenerator {
+            min_duration: Duration::from_millis(1),
+            event_producer: |_now, _prev| 42,
+        };
+
+        let mut handle = tick_gen.start(s).unwrap();
+        assert_eq!(r.recv().unwrap(), 42);
+        drop(r);
+
+        let drop_time = Instant::now();
+        while !handle.is_finished() {
+            assert!(
+                Instant::now() - drop_time < Duration::from_millis(500),
+                "Failed to stop within 500 ms of the receiver being dropped"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(handle.stop().is_ok());
+    }
 }
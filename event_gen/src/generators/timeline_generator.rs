@@ -0,0 +1,525 @@
+//! Emits named events at mission relative times (T+4.2 s) and at wall clock times.
+//!
+//! A timeline can be loaded from a TOML file with one table per event, either with a mission
+//! time `t` in seconds relative to T0 (negative for countdown events) or a UTC time of day:
+//!
+//! ```toml
+//! [burnout_expected]
+//! t = 4.2
+//!
+//! [max_drogue_time]
+//! t = 30.0
+//!
+//! [pad_power_on]
+//! utc = "14:30:00"
+//! ```
+//!
+//! Mission relative events are only emitted once T0 is known. It can be set before starting the
+//! generator, e.g. to the planned launch time, and re-anchored at any time through a
+//! [`TimelineControl`], e.g. once liftoff has been detected. Events that have already been
+//! emitted are not emitted again after re-anchoring, events which are overdue after re-anchoring
+//! are emitted right away. Wall clock events are emitted at the next occurrence of their time of
+//! day after the generator is started.
+
+use crate::event_generator::{EventGenHandle, EventGenerator};
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::{CountingSender, GenStats, StatsRecorder};
+
+use std::convert::Infallible;
+use std::fmt;
+use std::marker::Send;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use toml::Value;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// How long the timeline sleeps when nothing is due, anchoring wakes it up earlier.
+const IDLE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimelineTime {
+    /// Seconds relative to T0, negative before T0.
+    Mission(f64),
+    /// Time since midnight UTC.
+    UtcTimeOfDay(Duration),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineEntry {
+    pub name: String,
+    pub at: TimelineTime,
+}
+
+#[derive(Debug)]
+pub enum TimelineError {
+    Io(std::io::Error),
+    Toml(String),
+    /// The definition of the event `name` is invalid.
+    Invalid {
+        name: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineError::Io(e) => write!(f, "failed to read timeline: {e}"),
+            TimelineError::Toml(e) => write!(f, "failed to parse timeline: {e}"),
+            TimelineError::Invalid { name, reason } => {
+                write!(f, "invalid timeline event '{name}': {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timeline {
+    pub entries: Vec<TimelineEntry>,
+}
+
+impl Timeline {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TimelineError> {
+        let toml = std::fs::read_to_string(path).map_err(TimelineError::Io)?;
+        Self::from_toml(&toml)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, TimelineError> {
+        let value = toml
+            .parse::<Value>()
+            .map_err(|e| TimelineError::Toml(e.to_string()))?;
+        let Value::Table(table) = value else {
+            return Err(TimelineError::Toml("expected a table".to_string()));
+        };
+
+        let mut entries = Vec::new();
+        for (name, definition) in table {
+            let invalid = |reason: &str| TimelineError::Invalid {
+                name: name.clone(),
+                reason: reason.to_string(),
+            };
+            let Value::Table(definition) = definition else {
+                return Err(invalid("expected a table"));
+            };
+
+            let at = match (definition.get("t"), definition.get("utc")) {
+                (Some(Value::Float(t)), None) => TimelineTime::Mission(*t),
+                (Some(Value::Integer(t)), None) => TimelineTime::Mission(*t as f64),
+                (None, Some(Value::String(utc))) => TimelineTime::UtcTimeOfDay(
+                    parse_time_of_day(utc).ok_or_else(|| invalid("utc must be \"HH:MM:SS\""))?,
+                ),
+                (Some(_), Some(_)) => return Err(invalid("both t and utc are given")),
+                (None, None) => return Err(invalid("either t or utc is required")),
+                _ => return Err(invalid("t must be a number and utc a string")),
+            };
+            entries.push(TimelineEntry { name, at });
+        }
+        Ok(Self { entries })
+    }
+}
+
+fn parse_time_of_day(s: &str) -> Option<Duration> {
+    let mut parts = s.split(':').map(str::parse::<u64>);
+    let (h, m, sec) = (
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next().unwrap_or(Ok(0)).ok()?,
+    );
+    if parts.next().is_some() || h >= 24 || m >= 60 || sec >= 60 {
+        return None;
+    }
+    Some(Duration::from_secs(h * 3600 + m * 60 + sec))
+}
+
+/// The next instant at which the UTC clock shows `time_of_day`.
+fn next_occurrence(time_of_day: Duration, now: Instant) -> Instant {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let day = Duration::from_secs(SECONDS_PER_DAY);
+    let today = Duration::from_secs(since_epoch.as_secs() % SECONDS_PER_DAY)
+        + Duration::from_nanos(u64::from(since_epoch.subsec_nanos()));
+    if time_of_day >= today {
+        now + (time_of_day - today)
+    } else {
+        now + (day - today) + time_of_day
+    }
+}
+
+struct Scheduled {
+    entry: TimelineEntry,
+    /// Resolved time of wall clock events
+    wall_clock: Option<Instant>,
+    emitted: bool,
+}
+
+struct State {
+    entries: Vec<Scheduled>,
+    t0: Option<Instant>,
+}
+
+impl State {
+    fn due(&self, scheduled: &Scheduled) -> Option<Instant> {
+        match scheduled.entry.at {
+            TimelineTime::Mission(t) => {
+                let t0 = self.t0?;
+                let offset = Duration::try_from_secs_f64(t.abs()).ok()?;
+                if t >= 0.0 {
+                    t0.checked_add(offset)
+                } else {
+                    // Instants before the start of the clock cannot be represented, those
+                    // events are overdue anyway.
+                    Some(t0.checked_sub(offset).unwrap_or_else(Instant::now))
+                }
+            }
+            TimelineTime::UtcTimeOfDay(_) => scheduled.wall_clock,
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    timer: Mutex<Option<TimerHandle>>,
+}
+
+pub struct TimelineGenerator<T: Send> {
+    shared: Arc<Shared>,
+    event_producer: fn(&TimelineEntry) -> T,
+}
+
+impl<T: Send> TimelineGenerator<T> {
+    pub fn new(timeline: Timeline, event_producer: fn(&TimelineEntry) -> T) -> Self {
+        let entries = timeline
+            .entries
+            .into_iter()
+            .map(|entry| Scheduled {
+                entry,
+                wall_clock: None,
+                emitted: false,
+            })
+            .collect();
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State { entries, t0: None }),
+                timer: Mutex::new(None),
+            }),
+            event_producer,
+        }
+    }
+
+    /// Returns a handle to re-anchor and query the timeline, also after it has been started.
+    pub fn control(&self) -> TimelineControl {
+        TimelineControl {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// A not yet emitted event of a timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpcomingEvent {
+    pub name: String,
+    pub at: TimelineTime,
+    /// Time until the event is due, zero if it is overdue and `None` while T0 is unknown.
+    pub due_in: Option<Duration>,
+}
+
+/// Re-anchors and queries a timeline, see the [module documentation](self).
+#[derive(Clone)]
+pub struct TimelineControl {
+    shared: Arc<Shared>,
+}
+
+impl TimelineControl {
+    /// Sets T0 to `t0`, e.g. to the time liftoff was detected.
+    pub fn anchor(&self, t0: Instant) {
+        let next_due = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.t0 = Some(t0);
+            state
+                .entries
+                .iter()
+                .filter(|s| !s.emitted)
+                .filter_map(|s| state.due(s))
+                .min()
+        };
+        if let (Some(timer), Some(next_due)) =
+            (self.shared.timer.lock().unwrap().as_ref(), next_due)
+        {
+            timer.wake_at(next_due);
+        }
+    }
+
+    /// Sets T0 to now.
+    pub fn anchor_now(&self) {
+        self.anchor(Instant::now());
+    }
+
+    /// Seconds since T0, negative before T0 and `None` while T0 is unknown.
+    pub fn mission_time(&self) -> Option<f64> {
+        let t0 = self.shared.state.lock().unwrap().t0?;
+        let now = Instant::now();
+        Some(if now >= t0 {
+            (now - t0).as_secs_f64()
+        } else {
+            -(t0 - now).as_secs_f64()
+        })
+    }
+
+    /// Events which have not been emitted yet, the ones with a known time first and in order.
+    pub fn upcoming(&self) -> Vec<UpcomingEvent> {
+        let state = self.shared.state.lock().unwrap();
+        let now = Instant::now();
+        let mut upcoming: Vec<(Option<Instant>, UpcomingEvent)> = state
+            .entries
+            .iter()
+            .filter(|s| !s.emitted)
+            .map(|s| {
+                let due = state.due(s);
+                let event = UpcomingEvent {
+                    name: s.entry.name.clone(),
+                    at: s.entry.at,
+                    due_in: due.map(|due| due.saturating_duration_since(now)),
+                };
+                (due, event)
+            })
+            .collect();
+        upcoming.sort_by_key(|(due, _)| (due.is_none(), *due));
+        upcoming.into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+pub struct TimelineGenHandle {
+    shared: Arc<Shared>,
+    stats: Arc<StatsRecorder>,
+}
+
+impl EventGenHandle for TimelineGenHandle {
+    fn stop(&mut self) -> thread::Result<()> {
+        let timer = self.shared.timer.lock().unwrap().take();
+        match timer {
+            Some(mut timer) => timer.stop(),
+            None => Ok(()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.shared
+            .timer
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(TimerHandle::is_finished)
+    }
+
+    fn stats(&self) -> GenStats {
+        self.stats.snapshot()
+    }
+}
+
+impl<T: 'static + Send> TimelineGenerator<T> {
+    /// Sets T0 before the generator is started.
+    pub fn with_t0(self, t0: Instant) -> Self {
+        self.shared.state.lock().unwrap().t0 = Some(t0);
+        self
+    }
+
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> TimelineGenHandle {
+        let send_handle = CountingSender::new(send_handle);
+        let stats = send_handle.recorder();
+        let now = Instant::now();
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            for scheduled in &mut state.entries {
+                if let TimelineTime::UtcTimeOfDay(time_of_day) = scheduled.entry.at {
+                    scheduled.wall_clock = Some(next_occurrence(time_of_day, now));
+                }
+            }
+        }
+
+        let shared = self.shared.clone();
+        let event_producer = self.event_producer;
+        let timer = scheduler.schedule(now, move |now| {
+            let mut state = shared.state.lock().unwrap();
+            let mut due: Vec<(Instant, usize)> = state
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| !s.emitted)
+                .filter_map(|(i, s)| state.due(s).map(|due| (due, i)))
+                .collect();
+            due.sort_unstable();
+
+            let mut next = None;
+            for (at, i) in due {
+                if at > now {
+                    next = Some(at);
+                    break;
+                }
+                state.entries[i].emitted = true;
+                let event = event_producer(&state.entries[i].entry);
+                send_handle.send(event).ok()?;
+            }
+
+            if state.entries.iter().all(|s| s.emitted) {
+                None
+            } else {
+                Some(next.unwrap_or(now + IDLE_INTERVAL))
+            }
+        });
+        *self.shared.timer.lock().unwrap() = Some(timer);
+
+        TimelineGenHandle {
+            shared: self.shared,
+            stats,
+        }
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for TimelineGenerator<T> {
+    type Handle = TimelineGenHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn name(entry: &TimelineEntry) -> String {
+        entry.name.clone()
+    }
+
+    #[test]
+    fn parses_toml() {
+        let timeline = Timeline::from_toml(
+            r#"
+            [burnout_expected]
+            t = 4.2
+
+            [countdown]
+            t = -10
+
+            [pad_power_on]
+            utc = "14:30:05"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            timeline.entries,
+            vec![
+                TimelineEntry {
+                    name: "burnout_expected".to_string(),
+                    at: TimelineTime::Mission(4.2)
+                },
+                TimelineEntry {
+                    name: "countdown".to_string(),
+                    at: TimelineTime::Mission(-10.0)
+                },
+                TimelineEntry {
+                    name: "pad_power_on".to_string(),
+                    at: TimelineTime::UtcTimeOfDay(Duration::from_secs(14 * 3600 + 30 * 60 + 5))
+                },
+            ]
+        );
+
+        assert!(matches!(
+            Timeline::from_toml("[bad]\nutc = \"25:00:00\""),
+            Err(TimelineError::Invalid { .. })
+        ));
+        assert!(matches!(
+            Timeline::from_toml("[bad]\nt = 1\nutc = \"10:00:00\""),
+            Err(TimelineError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn waits_for_anchor() {
+        let timeline = Timeline::from_toml("[liftoff]\nt = 0\n[burnout]\nt = 0.05").unwrap();
+        let generator = TimelineGenerator::new(timeline, name);
+        let control = generator.control();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        assert!(r.recv_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(control.mission_time(), None);
+        assert_eq!(control.upcoming()[0].due_in, None);
+
+        control.anchor_now();
+        assert_eq!(r.recv().unwrap(), "liftoff");
+        let upcoming = control.upcoming();
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].name, "burnout");
+        assert!(upcoming[0].due_in.unwrap() <= Duration::from_millis(50));
+
+        let anchored = Instant::now();
+        assert_eq!(r.recv().unwrap(), "burnout");
+        assert!(anchored.elapsed() < Duration::from_millis(100));
+        assert!(control.mission_time().unwrap() >= 0.05);
+
+        // Everything has been emitted
+        assert!(r.recv().is_err());
+        assert_eq!(handle.stats().events_sent, 2);
+        assert!(handle.is_finished());
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn re_anchors() {
+        let timeline = Timeline::from_toml("[early]\nt = 0.02\n[late]\nt = 10.0").unwrap();
+        let generator = TimelineGenerator::new(timeline, name).with_t0(Instant::now());
+        let control = generator.control();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        assert_eq!(r.recv().unwrap(), "early");
+        // Liftoff was detected 10 s earlier than planned
+        control.anchor(Instant::now() - Duration::from_secs(10));
+        assert_eq!(r.recv_timeout(Duration::from_millis(100)).unwrap(), "late");
+        assert!(r.recv().is_err());
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn emits_wall_clock_events() {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let soon = Duration::from_secs((since_epoch.as_secs() + 1) % SECONDS_PER_DAY);
+        let timeline = Timeline {
+            entries: vec![TimelineEntry {
+                name: "pad".to_string(),
+                at: TimelineTime::UtcTimeOfDay(soon),
+            }],
+        };
+        let generator = TimelineGenerator::new(timeline, name);
+        let control = generator.control();
+        let (s, r) = mpsc::channel();
+        let mut handle = generator.start(s).unwrap();
+
+        assert!(control.upcoming()[0].due_in.unwrap() <= Duration::from_secs(1));
+        assert_eq!(r.recv_timeout(Duration::from_secs(2)).unwrap(), "pad");
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn stop_ends_timeline() {
+        let timeline = Timeline::from_toml("[later]\nt = 60").unwrap();
+        let (s, r) = mpsc::channel::<String>();
+        let mut handle = TimelineGenerator::new(timeline, name)
+            .with_t0(Instant::now())
+            .start(s)
+            .unwrap();
+        assert!(!handle.is_finished());
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+        assert!(r.recv().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Detects when a sensor or link goes silent.
+//!
+//! The watchdog is armed when it is started and has to be kicked through a [`WatchdogFeeder`]
+//! at least once per timeout window. If it is not, a [`WatchdogEvent::Timeout`] is emitted. The
+//! next kick emits a [`WatchdogEvent::Recovered`] and re-arms the watchdog.
+//!
+//! Feeders are cheap to clone and can be moved into sensor driver threads or into aurora_hal
+//! callbacks (`Box<dyn Fn() + Send + Sync>`), e.g. to kick the watchdog on every `set!` of a
+//! sensor value.
+
+use crate::event_generator::{EventGenHandle, EventGenerator};
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::{CountingSender, GenStats};
+
+use std::convert::Infallible;
+use std::marker::Send;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// No kick arrived for `silent_for`, which is at least the timeout.
+    Timeout { silent_for: Duration },
+    /// A kick arrived after a timeout, `silent_for` after the last kick before the timeout.
+    Recovered { silent_for: Duration },
+}
+
+struct Watch<T> {
+    last_kick: Instant,
+    timed_out: bool,
+    send_handle: Option<CountingSender<T>>,
+}
+
+struct Shared<T> {
+    watch: Mutex<Watch<T>>,
+    event_producer: fn(WatchdogEvent) -> T,
+}
+
+impl<T> Shared<T> {
+    /// Sends the event, drops the sender if the receiver is gone.
+    fn emit(&self, watch: &mut Watch<T>, event: WatchdogEvent) {
+        if let Some(send_handle) = &watch.send_handle {
+            if send_handle.send((self.event_producer)(event)).is_err() {
+                watch.send_handle = None;
+            }
+        }
+    }
+}
+
+pub struct WatchdogGenerator<T: Send> {
+    timeout: Duration,
+    shared: Arc<Shared<T>>,
+}
+
+/// Kicks a watchdog, see the [module documentation](self).
+pub struct WatchdogFeeder<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for WatchdogFeeder<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> WatchdogFeeder<T> {
+    pub fn kick(&self) {
+        let mut watch = self.shared.watch.lock().unwrap();
+        let now = Instant::now();
+        if watch.timed_out {
+            watch.timed_out = false;
+            let silent_for = now - watch.last_kick;
+            self.shared
+                .emit(&mut watch, WatchdogEvent::Recovered { silent_for });
+        }
+        watch.last_kick = now;
+    }
+}
+
+impl<T: Send> WatchdogGenerator<T> {
+    /// Creates a watchdog emitting events produced by `event_producer` and the feeder to kick it.
+    pub fn new(
+        timeout: Duration,
+        event_producer: fn(WatchdogEvent) -> T,
+    ) -> (Self, WatchdogFeeder<T>) {
+        let shared = Arc::new(Shared {
+            watch: Mutex::new(Watch {
+                last_kick: Instant::now(),
+                timed_out: false,
+                send_handle: None,
+            }),
+            event_producer,
+        });
+        let feeder = WatchdogFeeder {
+            shared: shared.clone(),
+        };
+        (Self { timeout, shared }, feeder)
+    }
+
+    pub fn feeder(&self) -> WatchdogFeeder<T> {
+        WatchdogFeeder {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+pub struct WatchdogGenHandle<T> {
+    timer: TimerHandle,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> EventGenHandle for WatchdogGenHandle<T> {
+    fn stop(&mut self) -> thread::Result<()> {
+        // Feeders may outlive the generator, make sure they stop sending as well.
+        self.shared.watch.lock().unwrap().send_handle = None;
+        self.timer.stop()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.timer.is_finished()
+    }
+
+    fn stats(&self) -> GenStats {
+        self.timer.stats()
+    }
+}
+
+impl<T: 'static + Send> WatchdogGenerator<T> {
+    /// Starts the generator on the given `scheduler` instead of the global one.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> WatchdogGenHandle<T> {
+        let timeout = self.timeout;
+        let shared = self.shared;
+        let send_handle = CountingSender::new(send_handle);
+        let recorder = send_handle.recorder();
+        let start = {
+            let mut watch = shared.watch.lock().unwrap();
+            watch.send_handle = Some(send_handle);
+            watch.last_kick = Instant::now();
+            watch.timed_out = false;
+            watch.last_kick
+        };
+
+        let task_shared = shared.clone();
+        let timer = scheduler
+            .schedule(start + timeout, move |now| {
+                let mut watch = task_shared.watch.lock().unwrap();
+                // Stopped or the receiver is gone
+                watch.send_handle.as_ref()?;
+                if watch.timed_out {
+                    // Only a kick can recover the watchdog, check back later to re-arm it.
+                    return Some(now + timeout);
+                }
+
+                let deadline = watch.last_kick + timeout;
+                if now < deadline {
+                    return Some(deadline);
+                }
+                watch.timed_out = true;
+                let silent_for = now - watch.last_kick;
+                task_shared.emit(&mut watch, WatchdogEvent::Timeout { silent_for });
+                watch.send_handle.as_ref().map(|_| now + timeout)
+            })
+            .with_stats(recorder);
+
+        WatchdogGenHandle { timer, shared }
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for WatchdogGenerator<T> {
+    type Handle = WatchdogGenHandle<T>;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Silent,
+        Back,
+    }
+
+    fn to_event(event: WatchdogEvent) -> Event {
+        match event {
+            WatchdogEvent::Timeout { .. } => Event::Silent,
+            WatchdogEvent::Recovered { .. } => Event::Back,
+        }
+    }
+
+    #[test]
+    fn times_out_without_kicks() {
+        let (s, r) = mpsc::channel();
+        let (watchdog, _feeder) = WatchdogGenerator::new(Duration::from_millis(30), |e| e);
+        let start = Instant::now();
+        let mut handle = watchdog.start(s).unwrap();
+
+        match r.recv().unwrap() {
+            WatchdogEvent::Timeout { silent_for } => {
+                assert!(silent_for >= Duration::from_millis(30));
+                assert!(start.elapsed() < Duration::from_millis(100));
+            }
+            e => panic!("unexpected event {:?}", e),
+        }
+        // Only one timeout until the next kick
+        assert!(r.recv_timeout(Duration::from_millis(80)).is_err());
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn kicks_from_another_thread_keep_it_quiet() {
+        let (s, r) = mpsc::channel();
+        let (watchdog, feeder) = WatchdogGenerator::new(Duration::from_millis(40), to_event);
+        let mut handle = watchdog.start(s).unwrap();
+
+        let driver = thread::spawn(move || {
+            for _ in 0..10 {
+                feeder.kick();
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        driver.join().unwrap();
+        assert!(r.try_recv().is_err());
+
+        assert_eq!(r.recv().unwrap(), Event::Silent);
+        assert_eq!(handle.stats().events_sent, 1);
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn recovers_and_rearms() {
+        let (s, r) = mpsc::channel();
+        let (watchdog, feeder) = WatchdogGenerator::new(Duration::from_millis(20), to_event);
+        let mut handle = watchdog.start(s).unwrap();
+
+        // The same type aurora_hal uses for callbacks
+        let callback: Box<dyn Fn() + Send + Sync> = Box::new(move || feeder.kick());
+
+        assert_eq!(r.recv().unwrap(), Event::Silent);
+        callback();
+        assert_eq!(r.recv().unwrap(), Event::Back);
+        assert_eq!(r.recv().unwrap(), Event::Silent);
+
+        assert!(handle.stop().is_ok());
+        callback();
+        assert!(r.recv().is_err());
+    }
+}
@@ -1,2 +1,8 @@
 pub mod event_generator;
+pub mod event_queue;
+pub mod framing;
 pub mod generators;
+mod rng;
+pub mod scheduler;
+pub mod stats;
+pub mod supervisor;
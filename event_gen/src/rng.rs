@@ -0,0 +1,72 @@
+//! A small seedable pseudo random number generator (SplitMix64).
+//!
+//! The randomized generators use this instead of an external crate so that a seed reproduces
+//! the same sequence of faults regardless of dependency versions.
+
+use std::time::Duration;
+
+pub(crate) struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in `[0, 1)`.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Returns `true` with the given probability.
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+
+    /// Uniformly distributed in `[0, n)`, `n` must not be zero.
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Uniformly distributed in `[min, max]`.
+    pub(crate) fn duration_between(&mut self, min: Duration, max: Duration) -> Duration {
+        min + max.saturating_sub(min).mul_f64(self.next_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_reproducible() {
+        let a: Vec<u64> = {
+            let mut rng = SeededRng::new(7);
+            (0..10).map(|_| rng.next_u64()).collect()
+        };
+        let mut rng = SeededRng::new(7);
+        assert!(a.iter().all(|x| *x == rng.next_u64()));
+        assert_ne!(SeededRng::new(8).next_u64(), a[0]);
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let mut rng = SeededRng::new(0);
+        for _ in 0..1000 {
+            let f = rng.next_f64();
+            assert!((0.0..1.0).contains(&f));
+            assert!(rng.below(3) < 3);
+            let d = rng.duration_between(Duration::from_millis(1), Duration::from_millis(2));
+            assert!(d >= Duration::from_millis(1) && d <= Duration::from_millis(2));
+        }
+    }
+}
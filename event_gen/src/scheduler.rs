@@ -0,0 +1,487 @@
+//! A shared timer thread for time based event generators.
+//!
+//! Instead of spawning one OS thread per generator, time based generators register a task with a
+//! [`Scheduler`]. A scheduler owns a single timer thread which keeps all pending tasks in a heap
+//! ordered by their deadline and runs each task once its deadline has passed. A task may ask to
+//! be run again by returning its next deadline.
+//!
+//! Generators started through the [`EventGenerator`](crate::event_generator::EventGenerator)
+//! trait use the process-wide [`Scheduler::global`] instance.
+//!
+//! Tasks run one after another on the timer thread, so a task which blocks delays every other
+//! task of its scheduler. Generators whose sources may block, e.g. reads from a slow device or
+//! user supplied closures, should be started on a scheduler of their own with `start_with`.
+
+use crate::event_generator::EventGenHandle;
+use crate::stats::{GenStats, StatsRecorder};
+
+use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+type TaskFn = Box<dyn FnMut(Instant) -> Option<Instant> + Send>;
+
+struct Task {
+    run: TaskFn,
+    slot: Arc<TimerSlot>,
+    /// Queue entries with a different deadline are outdated and skipped.
+    deadline: Instant,
+}
+
+#[derive(Default)]
+struct Outcome {
+    done: bool,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+#[derive(Default)]
+struct TimerSlot {
+    cancelled: AtomicBool,
+    outcome: Mutex<Outcome>,
+    finished: Condvar,
+}
+
+impl TimerSlot {
+    /// Drops `task` while holding the outcome lock, so that whoever notices the task's captures
+    /// being dropped, e.g. a receiver whose sender is gone, also sees the task as finished.
+    fn finish(&self, task: Task, panic: Option<Box<dyn Any + Send>>) {
+        let mut outcome = self.outcome.lock().unwrap();
+        drop(task);
+        outcome.done = true;
+        outcome.panic = panic;
+        self.finished.notify_all();
+    }
+}
+
+#[derive(Default)]
+struct State {
+    queue: BinaryHeap<Reverse<(Instant, u64)>>,
+    tasks: HashMap<u64, Task>,
+    next_id: u64,
+    /// Earliest deadline requested through [`TimerHandle::wake_at`] for the running task.
+    running_wake_request: Option<Instant>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wakeup: Condvar,
+    /// Set once the timer thread has started, tasks run on this thread.
+    timer_thread: OnceLock<ThreadId>,
+}
+
+struct Inner {
+    shared: Arc<Shared>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.wakeup.notify_all();
+        if let Some(thread) = self.thread.take() {
+            // Tasks run under `catch_unwind`, so the timer thread itself does not panic.
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A handle to a timer thread that runs scheduled tasks.
+///
+/// Cloning a `Scheduler` yields another handle to the same timer thread. The thread shuts down
+/// once the last handle is dropped, dropping all tasks which have not finished yet.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    /// Creates a new scheduler with its own timer thread.
+    pub fn new() -> Self {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("event_gen-scheduler".to_string())
+            .spawn(move || run_timer_thread(&thread_shared))
+            .expect("Failed to spawn the scheduler timer thread");
+
+        Self {
+            inner: Arc::new(Inner {
+                shared,
+                thread: Some(thread),
+            }),
+        }
+    }
+
+    /// Returns the process-wide scheduler used by the generators in this crate.
+    pub fn global() -> &'static Scheduler {
+        static GLOBAL: OnceLock<Scheduler> = OnceLock::new();
+        GLOBAL.get_or_init(Scheduler::new)
+    }
+
+    /// Schedules `task` to run at `deadline`.
+    ///
+    /// The task receives the time at which it is run. If it returns `Some(next)` it is run
+    /// again at `next`, if it returns `None` it is dropped and the returned handle reports it
+    /// as finished.
+    pub fn schedule<F>(&self, deadline: Instant, task: F) -> TimerHandle
+    where
+        F: FnMut(Instant) -> Option<Instant> + Send + 'static,
+    {
+        let slot = Arc::new(TimerSlot::default());
+        let shared = &self.inner.shared;
+
+        let id = {
+            let mut state = shared.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.queue.push(Reverse((deadline, id)));
+            state.tasks.insert(
+                id,
+                Task {
+                    run: Box::new(task),
+                    slot: slot.clone(),
+                    deadline,
+                },
+            );
+            id
+        };
+        shared.wakeup.notify_all();
+
+        TimerHandle {
+            id,
+            slot,
+            shared: shared.clone(),
+            stats: Arc::default(),
+        }
+    }
+}
+
+fn run_timer_thread(shared: &Shared) {
+    let _ = shared.timer_thread.set(thread::current().id());
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.shutdown {
+            for (_, task) in state.tasks.drain() {
+                task.slot.clone().finish(task, None);
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        let deadline = match state.queue.peek() {
+            Some(Reverse((deadline, _))) => *deadline,
+            None => {
+                state = shared.wakeup.wait(state).unwrap();
+                continue;
+            }
+        };
+        if deadline > now {
+            state = shared.wakeup.wait_timeout(state, deadline - now).unwrap().0;
+            continue;
+        }
+
+        let Some(Reverse((deadline, id))) = state.queue.pop() else {
+            continue;
+        };
+        // Tasks which have been cancelled while waiting are already removed from the map.
+        match state.tasks.get(&id) {
+            Some(task) if task.deadline == deadline => {}
+            _ => continue,
+        }
+        let Some(mut task) = state.tasks.remove(&id) else {
+            continue;
+        };
+        state.running_wake_request = None;
+
+        // Run the task without holding the lock so that tasks and handles on other threads
+        // can (un)register timers in the meantime.
+        drop(state);
+        let result = if task.slot.cancelled.load(Ordering::Acquire) {
+            Ok(None)
+        } else {
+            panic::catch_unwind(AssertUnwindSafe(|| (task.run)(Instant::now())))
+        };
+        state = shared.state.lock().unwrap();
+
+        let slot = task.slot.clone();
+        let wake_request = state.running_wake_request.take();
+        match result {
+            Ok(Some(next)) if !slot.cancelled.load(Ordering::Acquire) => {
+                let next = wake_request.map_or(next, |wake| wake.min(next));
+                task.deadline = next;
+                state.queue.push(Reverse((next, id)));
+                state.tasks.insert(id, task);
+            }
+            Ok(_) => slot.finish(task, None),
+            Err(payload) => slot.finish(task, Some(payload)),
+        }
+    }
+}
+
+/// Handle to a task registered with a [`Scheduler`].
+pub struct TimerHandle {
+    id: u64,
+    slot: Arc<TimerSlot>,
+    shared: Arc<Shared>,
+    stats: Arc<StatsRecorder>,
+}
+
+impl TimerHandle {
+    /// Reports the statistics recorded by `stats`, usually the recorder of the generator's
+    /// [`CountingSender`](crate::stats::CountingSender).
+    pub fn with_stats(mut self, stats: Arc<StatsRecorder>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Runs the task at `deadline` if that is earlier than the deadline it is waiting for.
+    ///
+    /// This allows tasks whose deadline depends on state changed from other threads to react
+    /// to the change. Has no effect once the task is finished.
+    pub fn wake_at(&self, deadline: Instant) {
+        wake_at(&self.shared, &self.slot, self.id, deadline);
+    }
+
+    /// Returns a waker for the task that can be moved to other threads, e.g. to the thread
+    /// producing the data the task waits for.
+    pub fn waker(&self) -> TimerWaker {
+        TimerWaker {
+            id: self.id,
+            slot: self.slot.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Whether the caller runs on the timer thread of the task's scheduler, i.e. inside a task.
+    pub(crate) fn on_timer_thread(&self) -> bool {
+        self.shared.timer_thread.get() == Some(&thread::current().id())
+    }
+}
+
+/// Wakes a task registered with a [`Scheduler`] without being able to stop it, see
+/// [`TimerHandle::waker`].
+#[derive(Clone)]
+pub struct TimerWaker {
+    id: u64,
+    slot: Arc<TimerSlot>,
+    shared: Arc<Shared>,
+}
+
+impl TimerWaker {
+    /// Same as [`TimerHandle::wake_at`].
+    pub fn wake_at(&self, deadline: Instant) {
+        wake_at(&self.shared, &self.slot, self.id, deadline);
+    }
+}
+
+fn wake_at(shared: &Shared, slot: &TimerSlot, id: u64, deadline: Instant) {
+    let mut state = shared.state.lock().unwrap();
+    if let Some(task) = state.tasks.get_mut(&id) {
+        if deadline < task.deadline {
+            task.deadline = deadline;
+            state.queue.push(Reverse((deadline, id)));
+            shared.wakeup.notify_all();
+        }
+    } else if !slot.outcome.lock().unwrap().done {
+        // The task is running right now and is rescheduled once it returns.
+        let request = state.running_wake_request.get_or_insert(deadline);
+        *request = (*request).min(deadline);
+    }
+}
+
+impl EventGenHandle for TimerHandle {
+    fn stop(&mut self) -> thread::Result<()> {
+        self.slot.cancelled.store(true, Ordering::Release);
+
+        // If the task is waiting for its deadline it can be dropped right away, otherwise it is
+        // currently running and the timer thread drops it once it returns.
+        let pending = self.shared.state.lock().unwrap().tasks.remove(&self.id);
+        if let Some(task) = pending {
+            self.slot.finish(task, None);
+        }
+
+        let mut outcome = self.slot.outcome.lock().unwrap();
+        // Only one task runs at a time, so a task which is neither pending nor done while the
+        // timer thread stops it is the running task stopping itself, e.g. from a callback of
+        // its generator. The timer thread drops it once it returns, waiting here would deadlock.
+        if !outcome.done && self.on_timer_thread() {
+            return Ok(());
+        }
+        while !outcome.done {
+            outcome = self.slot.finished.wait(outcome).unwrap();
+        }
+        match outcome.panic.take() {
+            Some(payload) => Err(payload),
+            None => Ok(()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.slot.outcome.lock().unwrap().done
+    }
+
+    fn stats(&self) -> GenStats {
+        self.stats.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn runs_tasks_in_deadline_order() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+        let start = Instant::now();
+
+        for (i, delay) in [30, 10, 20].iter().enumerate() {
+            let s = s.clone();
+            scheduler.schedule(start + Duration::from_millis(*delay), move |_| {
+                s.send(i).unwrap();
+                None
+            });
+        }
+
+        let order: Vec<usize> = (0..3).map(|_| r.recv().unwrap()).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn reschedules_until_done() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+
+        let mut remaining = 5;
+        let handle = scheduler.schedule(Instant::now(), move |now| {
+            s.send(remaining).unwrap();
+            remaining -= 1;
+            (remaining > 0).then(|| now + Duration::from_millis(1))
+        });
+
+        let values: Vec<i32> = r.iter().collect();
+        assert_eq!(values, vec![5, 4, 3, 2, 1]);
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn stop_drops_pending_task() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel::<()>();
+
+        let mut handle = scheduler.schedule(Instant::now() + Duration::from_secs(60), move |_| {
+            s.send(()).unwrap();
+            None
+        });
+        assert!(!handle.is_finished());
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+        assert!(r.recv().is_err());
+    }
+
+    #[test]
+    fn panicking_task_does_not_stop_scheduler() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+
+        let mut panicking = scheduler.schedule(Instant::now(), |_| panic!("task failed"));
+        scheduler.schedule(Instant::now() + Duration::from_millis(5), move |_| {
+            s.send(42).unwrap();
+            None
+        });
+
+        assert_eq!(r.recv().unwrap(), 42);
+        assert!(panicking.stop().is_err());
+        assert!(panicking.stop().is_ok());
+    }
+
+    #[test]
+    fn wakes_task_early() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+
+        let start = Instant::now();
+        let handle = scheduler.schedule(start + Duration::from_secs(60), move |now| {
+            s.send(now).unwrap();
+            Some(now + Duration::from_secs(60))
+        });
+        handle.wake_at(start + Duration::from_millis(10));
+        // Later deadlines do not delay the task
+        handle.wake_at(start + Duration::from_secs(120));
+
+        let woken = r.recv().unwrap();
+        assert!(woken - start < Duration::from_secs(1));
+        assert!(r.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn task_can_stop_itself() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+
+        let own_handle: Arc<Mutex<Option<TimerHandle>>> = Arc::default();
+        let handle = {
+            let own_handle = own_handle.clone();
+            scheduler.schedule(Instant::now(), move |now| {
+                let stopped = own_handle.lock().unwrap().as_mut().map(|h| h.stop());
+                s.send(stopped.is_some()).unwrap();
+                Some(now + Duration::from_millis(1))
+            })
+        };
+        *own_handle.lock().unwrap() = Some(handle);
+
+        // The first run may happen before the handle is stored
+        while !r.recv_timeout(Duration::from_secs(5)).unwrap() {}
+        assert!(r.recv_timeout(Duration::from_secs(5)).is_err());
+        assert!(own_handle.lock().unwrap().as_ref().unwrap().is_finished());
+    }
+
+    #[test]
+    fn waker_wakes_task_from_other_threads() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+
+        let start = Instant::now();
+        let handle = scheduler.schedule(start + Duration::from_secs(60), move |now| {
+            s.send(now).unwrap();
+            None
+        });
+        let waker = handle.waker();
+        thread::spawn(move || waker.wake_at(Instant::now()))
+            .join()
+            .unwrap();
+
+        let woken = r.recv().unwrap();
+        assert!(woken - start < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn dropping_scheduler_drops_tasks() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel::<()>();
+
+        let handle = scheduler.schedule(Instant::now() + Duration::from_secs(60), move |_| {
+            s.send(()).unwrap();
+            None
+        });
+        drop(scheduler);
+
+        assert!(handle.is_finished());
+        assert!(r.recv().is_err());
+    }
+}
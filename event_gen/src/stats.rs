@@ -0,0 +1,106 @@
+//! Statistics kept by every generator about the events it sends.
+//!
+//! Generators send through a [`CountingSender`], which records every send in a shared
+//! [`StatsRecorder`]. The generator's handle holds on to the same recorder and returns a
+//! [`GenStats`] snapshot from [`EventGenHandle::stats`](crate::event_generator::EventGenHandle::stats).
+
+use std::sync::mpsc::{SendError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GenStats {
+    pub events_sent: u64,
+    pub last_send: Option<Instant>,
+    /// Sends that failed because the receiver had been dropped.
+    pub send_failures: u64,
+    /// Events that could not be produced on time, e.g. ticks of a [`TickGenerator`] which fired
+    /// more than a full interval late.
+    ///
+    /// [`TickGenerator`]: crate::generators::tick_generator::TickGenerator
+    pub overruns: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct StatsRecorder {
+    stats: Mutex<GenStats>,
+}
+
+impl StatsRecorder {
+    pub fn record_overrun(&self) {
+        self.stats.lock().unwrap().overruns += 1;
+    }
+
+    pub fn snapshot(&self) -> GenStats {
+        *self.stats.lock().unwrap()
+    }
+}
+
+/// A [`Sender`] which records its sends in a [`StatsRecorder`].
+pub struct CountingSender<T> {
+    send_handle: Sender<T>,
+    recorder: Arc<StatsRecorder>,
+}
+
+impl<T> Clone for CountingSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            send_handle: self.send_handle.clone(),
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+impl<T> CountingSender<T> {
+    pub fn new(send_handle: Sender<T>) -> Self {
+        Self {
+            send_handle,
+            recorder: Arc::default(),
+        }
+    }
+
+    pub fn send(&self, event: T) -> Result<(), SendError<T>> {
+        // Sending while holding the lock makes sure a receiver never sees an event before it
+        // has been counted.
+        let mut stats = self.recorder.stats.lock().unwrap();
+        let result = self.send_handle.send(event);
+        if result.is_ok() {
+            stats.events_sent += 1;
+            stats.last_send = Some(Instant::now());
+        } else {
+            stats.send_failures += 1;
+        }
+        result
+    }
+
+    pub fn recorder(&self) -> Arc<StatsRecorder> {
+        self.recorder.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn counts_sends() {
+        let (s, r) = mpsc::channel();
+        let sender = CountingSender::new(s);
+        let recorder = sender.recorder();
+        assert_eq!(recorder.snapshot(), GenStats::default());
+
+        sender.send(1).unwrap();
+        sender.clone().send(2).unwrap();
+        recorder.record_overrun();
+        let stats = recorder.snapshot();
+        assert_eq!(stats.events_sent, 2);
+        assert_eq!(stats.overruns, 1);
+        assert!(stats.last_send.is_some());
+
+        drop(r);
+        assert!(sender.send(3).is_err());
+        let stats = recorder.snapshot();
+        assert_eq!((stats.events_sent, stats.send_failures), (2, 1));
+    }
+}
@@ -0,0 +1,711 @@
+//! Watches generators, restarts the ones that fail and reports their health as events.
+//!
+//! A [`Supervisor`] is a generator itself. The generators it supervises are started with a clone
+//! of its sender, so their events and the supervisor's [`HealthEvent`]s end up in the same queue
+//! and the state machine can react to a failing sensor like to any other event.
+//!
+//! A generator that panics is restarted after a delay which doubles with every restart, see
+//! [`Backoff`]. A generator that ends on its own, e.g. because it ran out of events, is not
+//! restarted.
+
+use crate::event_generator::{EventGenHandle, EventGenerator};
+use crate::scheduler::{Scheduler, TimerHandle};
+use crate::stats::{CountingSender, GenStats};
+
+use std::any::Any;
+use std::convert::Infallible;
+use std::marker::Send;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// When and how often failed generators are restarted.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Delay before the first restart, doubled for every further restart.
+    pub initial: Duration,
+    pub max: Duration,
+    /// Number of restarts after which a generator is given up, `None` to restart forever.
+    pub max_restarts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            max_restarts: None,
+        }
+    }
+}
+
+impl Backoff {
+    fn delay(&self, restarts: u32) -> Duration {
+        self.initial
+            .checked_mul(1 << restarts.min(31))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Health {
+    /// The generator panicked or could not be started, `message` says why.
+    Failed { message: String },
+    /// The generator was started again, `restarts` counts all restarts so far.
+    Restarted { restarts: u32 },
+    /// The generator failed after its last allowed restart and is not restarted anymore.
+    GaveUp,
+    /// The generator ended on its own and is not restarted.
+    Finished,
+    /// The generator has not sent an event for `silent_for`, which exceeds the stall timeout.
+    Stalled { silent_for: Duration },
+    /// A stalled generator sent an event again.
+    Resumed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthEvent {
+    /// The name the generator was registered with.
+    pub generator: String,
+    pub health: Health,
+    /// Statistics of the generator when the event was reported. They start over on a restart.
+    pub stats: GenStats,
+}
+
+type BoxedHandle = Box<dyn EventGenHandle + Send>;
+/// Starts a new instance of a supervised generator, fails with the reason it could not start.
+type StartFn<T> = Box<dyn FnMut(Sender<T>) -> Result<BoxedHandle, String> + Send>;
+
+struct Child {
+    name: String,
+    handle: Option<BoxedHandle>,
+    started_at: Instant,
+    /// Whether the generator has been started before, successfully or not.
+    attempted: bool,
+    restarts: u32,
+    restart_at: Option<Instant>,
+    stalled: bool,
+    /// Whether the supervisor thread is about to stop or start the generator.
+    busy: bool,
+}
+
+impl Child {
+    fn stats(&self) -> GenStats {
+        self.handle
+            .as_ref()
+            .map_or_else(GenStats::default, |handle| handle.stats())
+    }
+}
+
+/// Work handed from the health checks on the timer thread to the supervisor thread.
+enum Work {
+    /// Stops the finished generator of the child with the given index.
+    Stop(usize),
+    /// Starts the generator of the child with the given index.
+    Start(usize),
+    /// Stops all generators because nobody listens anymore.
+    Shutdown,
+}
+
+pub struct Supervisor<T: Send> {
+    children: Vec<Child>,
+    starts: Vec<StartFn<T>>,
+    backoff: Backoff,
+    stall_timeout: Option<Duration>,
+    check_interval: Duration,
+    health_producer: fn(HealthEvent) -> T,
+}
+
+impl<T: 'static + Send> Supervisor<T> {
+    /// Creates a supervisor which turns health reports into events with `health_producer`.
+    pub fn new(health_producer: fn(HealthEvent) -> T) -> Self {
+        Self {
+            children: Vec::new(),
+            starts: Vec::new(),
+            backoff: Backoff::default(),
+            stall_timeout: None,
+            check_interval: Duration::from_millis(50),
+            health_producer,
+        }
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Reports generators which have not sent an event for `timeout` as stalled.
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// How often the supervised generators are checked, 50 ms by default.
+    pub fn check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Supervises the generators created by `generator`, which is called again for every restart.
+    pub fn supervise<G, F>(mut self, name: impl Into<String>, mut generator: F) -> Self
+    where
+        G: EventGenerator<T>,
+        G::Handle: Send + 'static,
+        F: FnMut() -> G + Send + 'static,
+    {
+        self.children.push(Child {
+            name: name.into(),
+            handle: None,
+            started_at: Instant::now(),
+            attempted: false,
+            restarts: 0,
+            restart_at: Some(Instant::now()),
+            stalled: false,
+            busy: false,
+        });
+        self.starts.push(Box::new(move |send_handle| {
+            match generator().start(send_handle) {
+                Ok(handle) => Ok(Box::new(handle)),
+                Err(e) => Err(e.to_string()),
+            }
+        }));
+        self
+    }
+
+    /// Starts the supervisor on the given `scheduler` instead of the global one.
+    ///
+    /// Only the health checks run on the scheduler's timer thread. The supervised generators are
+    /// stopped and started by a thread of the supervisor, so a generator which is slow to start
+    /// or to stop does not hold up the other tasks of the scheduler. A generator which cannot be
+    /// started is reported as failed and retried like one that panicked.
+    pub fn start_with(self, scheduler: &Scheduler, send_handle: Sender<T>) -> SupervisorHandle {
+        let children = Arc::new(Mutex::new(self.children));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let health_handle = CountingSender::new(send_handle.clone());
+        let recorder = health_handle.recorder();
+        let supervision = Arc::new(Supervision {
+            send_handle,
+            health_handle,
+            health_producer: self.health_producer,
+            backoff: self.backoff,
+            stall_timeout: self.stall_timeout,
+            stopped: stopped.clone(),
+        });
+
+        let (work_sender, work_receiver) = mpsc::channel();
+        let worker_supervision = supervision.clone();
+        let worker_children = children.clone();
+        let mut starts = self.starts;
+        let worker = thread::Builder::new()
+            .name("event_gen-supervisor".to_string())
+            .spawn(move || {
+                for work in work_receiver {
+                    let listening = match work {
+                        Work::Stop(index) => worker_supervision.stop(&worker_children, index),
+                        Work::Start(index) => {
+                            worker_supervision.start(&worker_children, index, &mut starts[index])
+                        }
+                        Work::Shutdown => false,
+                    };
+                    if !listening {
+                        let _ = shutdown(&worker_children);
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn the supervisor thread");
+
+        let check_interval = self.check_interval;
+        let task_children = children.clone();
+        let timer = scheduler.schedule(Instant::now(), move |now| {
+            let mut children = task_children.lock().unwrap();
+            for (index, child) in children.iter_mut().enumerate() {
+                let handed_off = match supervision.check(child, index, now) {
+                    Ok(None) => true,
+                    Ok(Some(work)) => work_sender.send(work).is_ok(),
+                    // Nobody listens anymore, neither to the health reports nor to the generators.
+                    Err(()) => {
+                        let _ = work_sender.send(Work::Shutdown);
+                        return None;
+                    }
+                };
+                if !handed_off {
+                    // The supervisor thread shut down after a failed report.
+                    return None;
+                }
+            }
+
+            let active = children
+                .iter()
+                .any(|child| child.busy || child.handle.is_some() || child.restart_at.is_some());
+            active.then_some(now + check_interval)
+        });
+
+        SupervisorHandle {
+            timer: timer.with_stats(recorder),
+            worker: Some(worker),
+            children,
+            stopped,
+        }
+    }
+}
+
+impl<T: 'static + Send> EventGenerator<T> for Supervisor<T> {
+    type Handle = SupervisorHandle;
+    type Error = Infallible;
+    fn start(self, send_handle: Sender<T>) -> Result<Self::Handle, Self::Error> {
+        Ok(self.start_with(Scheduler::global(), send_handle))
+    }
+}
+
+/// State of a running supervisor shared by all its children.
+struct Supervision<T> {
+    send_handle: Sender<T>,
+    health_handle: CountingSender<T>,
+    health_producer: fn(HealthEvent) -> T,
+    backoff: Backoff,
+    stall_timeout: Option<Duration>,
+    /// Set by [`SupervisorHandle::stop`] while holding the lock of the children.
+    stopped: Arc<AtomicBool>,
+}
+
+impl<T> Supervision<T> {
+    /// Checks on `child` from the timer thread and returns the work the supervisor thread has to
+    /// do for it, fails once the receiver is gone.
+    fn check(&self, child: &mut Child, index: usize, now: Instant) -> Result<Option<Work>, ()> {
+        if child.busy {
+            return Ok(None);
+        }
+        let work = match &child.handle {
+            Some(handle) if handle.is_finished() => Some(Work::Stop(index)),
+            Some(handle) => {
+                let Some(timeout) = self.stall_timeout else {
+                    return Ok(None);
+                };
+                let last_send = handle.stats().last_send.unwrap_or(child.started_at);
+                let silent_for = now.saturating_duration_since(last_send);
+                let reported = if silent_for >= timeout && !child.stalled {
+                    child.stalled = true;
+                    self.report(child, Health::Stalled { silent_for })
+                } else if silent_for < timeout && child.stalled {
+                    child.stalled = false;
+                    self.report(child, Health::Resumed)
+                } else {
+                    true
+                };
+                return if reported { Ok(None) } else { Err(()) };
+            }
+            None => match child.restart_at {
+                Some(restart_at) if restart_at <= now => {
+                    child.restart_at = None;
+                    if child.attempted {
+                        child.restarts += 1;
+                    }
+                    child.attempted = true;
+                    Some(Work::Start(index))
+                }
+                _ => None,
+            },
+        };
+        child.busy = work.is_some();
+        Ok(work)
+    }
+
+    /// Stops the finished generator of the child at `index` on the supervisor thread, returns
+    /// `false` once the receiver is gone.
+    fn stop(&self, children: &Mutex<Vec<Child>>, index: usize) -> bool {
+        let Some(mut handle) = children.lock().unwrap()[index].handle.take() else {
+            return true;
+        };
+        // Joining the generator may take a while, the health checks go on in the meantime.
+        let result = handle.stop();
+        let stats = handle.stats();
+
+        let mut children = children.lock().unwrap();
+        let child = &mut children[index];
+        child.busy = false;
+        match result {
+            Ok(()) => self.report_stats(child, Health::Finished, stats),
+            Err(payload) => self.failed(
+                child,
+                panic_message(payload.as_ref()),
+                stats,
+                Instant::now(),
+            ),
+        }
+    }
+
+    /// Starts the generator of the child at `index` on the supervisor thread, returns `false`
+    /// once the receiver is gone.
+    ///
+    /// Starts still queued when the supervisor is stopped are skipped. A generator whose start
+    /// was already under way is stopped right away, the supervisor is not around to stop it
+    /// later.
+    fn start(&self, children: &Mutex<Vec<Child>>, index: usize, start: &mut StartFn<T>) -> bool {
+        if self.stopped.load(Ordering::SeqCst) {
+            children.lock().unwrap()[index].busy = false;
+            return true;
+        }
+        let result = start(self.send_handle.clone());
+        let now = Instant::now();
+
+        let mut children = children.lock().unwrap();
+        let child = &mut children[index];
+        child.busy = false;
+        match result {
+            Ok(mut handle) if self.stopped.load(Ordering::SeqCst) => {
+                drop(children);
+                let _ = handle.stop();
+                true
+            }
+            Ok(handle) => {
+                child.handle = Some(handle);
+                child.started_at = now;
+                child.stalled = false;
+                child.restarts == 0
+                    || self.report(
+                        child,
+                        Health::Restarted {
+                            restarts: child.restarts,
+                        },
+                    )
+            }
+            Err(message) => self.failed(child, message, GenStats::default(), now),
+        }
+    }
+
+    /// Reports the failure and schedules the restart of `child`.
+    fn failed(&self, child: &mut Child, message: String, stats: GenStats, now: Instant) -> bool {
+        let give_up = self
+            .backoff
+            .max_restarts
+            .is_some_and(|max| child.restarts >= max);
+        if !give_up {
+            child.restart_at = Some(now + self.backoff.delay(child.restarts));
+        }
+        self.report_stats(child, Health::Failed { message }, stats)
+            && (!give_up || self.report_stats(child, Health::GaveUp, stats))
+    }
+
+    fn report(&self, child: &Child, health: Health) -> bool {
+        self.report_stats(child, health, child.stats())
+    }
+
+    fn report_stats(&self, child: &Child, health: Health, stats: GenStats) -> bool {
+        let event = HealthEvent {
+            generator: child.name.clone(),
+            health,
+            stats,
+        };
+        self.health_handle
+            .send((self.health_producer)(event))
+            .is_ok()
+    }
+}
+
+/// Stops all running children, returns the first panic of a child.
+fn stop_all(children: &mut [Child]) -> thread::Result<()> {
+    let mut result = Ok(());
+    for mut handle in children.iter_mut().filter_map(|child| child.handle.take()) {
+        result = result.and(handle.stop());
+    }
+    result
+}
+
+/// Stops all children for good once nobody listens anymore, without holding the lock while
+/// joining them.
+fn shutdown(children: &Mutex<Vec<Child>>) -> thread::Result<()> {
+    let mut handles = Vec::new();
+    for child in children.lock().unwrap().iter_mut() {
+        handles.extend(child.handle.take());
+        child.restart_at = None;
+        child.busy = false;
+    }
+    let mut result = Ok(());
+    for mut handle in handles {
+        result = result.and(handle.stop());
+    }
+    result
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+pub struct SupervisorHandle {
+    timer: TimerHandle,
+    worker: Option<thread::JoinHandle<()>>,
+    children: Arc<Mutex<Vec<Child>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl SupervisorHandle {
+    /// Statistics of every supervised generator by name.
+    pub fn generator_stats(&self) -> Vec<(String, GenStats)> {
+        self.children
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|child| (child.name.clone(), child.stats()))
+            .collect()
+    }
+}
+
+impl EventGenHandle for SupervisorHandle {
+    /// Stops the supervisor and all generators it supervises.
+    fn stop(&mut self) -> thread::Result<()> {
+        // Keeps the supervisor thread from starting generators which nobody would stop. Set under
+        // the lock, so a generator is either stored before stop_all below or stopped by the thread.
+        {
+            let _children = self.children.lock().unwrap();
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+        let supervisor = self.timer.stop();
+        // The supervisor thread ends once the health checks are dropped, after finishing what it
+        // was handed. It is not joined from the timer thread, where waiting for a generator which
+        // is slow to start would hold up the other tasks, it stops what it starts by itself.
+        if !self.timer.on_timer_thread() {
+            if let Some(worker) = self.worker.take() {
+                let _ = worker.join();
+            }
+        }
+        let children = stop_all(&mut self.children.lock().unwrap());
+        supervisor.and(children)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.timer.is_finished()
+    }
+
+    /// Statistics of the health events, see [`SupervisorHandle::generator_stats`] for the ones
+    /// of the supervised generators.
+    fn stats(&self) -> GenStats {
+        self.timer.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::one_shot_generator::OneShotGenerator;
+    use crate::generators::replay_generator::{LogFormat, ReplayGenerator, ReplaySpeed};
+    use crate::generators::tick_generator::TickGenerator;
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Tick,
+        Health(HealthEvent),
+    }
+
+    fn health(r: &mpsc::Receiver<Event>) -> Health {
+        loop {
+            match r.recv_timeout(Duration::from_secs(1)).unwrap() {
+                Event::Health(event) => return event.health,
+                Event::Tick => {}
+            }
+        }
+    }
+
+    #[test]
+    fn restarts_with_backoff_and_gives_up() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+        let start = Instant::now();
+        let mut handle = Supervisor::new(Event::Health)
+            .backoff(Backoff {
+                initial: Duration::from_millis(10),
+                max: Duration::from_millis(15),
+                max_restarts: Some(2),
+            })
+            .check_interval(Duration::from_millis(1))
+            .supervise("flaky", || TickGenerator {
+                min_duration: Duration::from_millis(1),
+                event_producer: |_now, _prev| -> Event { panic!("sensor gone") },
+            })
+            .start_with(&scheduler, s);
+
+        let failed = Health::Failed {
+            message: "sensor gone".to_string(),
+        };
+        assert_eq!(health(&r), failed);
+        assert_eq!(health(&r), Health::Restarted { restarts: 1 });
+        assert_eq!(health(&r), failed);
+        assert_eq!(health(&r), Health::Restarted { restarts: 2 });
+        assert_eq!(health(&r), failed);
+        assert_eq!(health(&r), Health::GaveUp);
+        // 10 ms before the first and 15 ms instead of 20 ms before the second restart
+        assert!(start.elapsed() >= Duration::from_millis(25));
+
+        assert!(r.recv().is_err());
+        assert!(handle.is_finished());
+        assert_eq!(handle.stats().events_sent, 6);
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn retries_generators_that_fail_to_start() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+        let mut handle = Supervisor::new(Event::Health)
+            .backoff(Backoff {
+                initial: Duration::from_millis(1),
+                max: Duration::from_millis(1),
+                max_restarts: Some(1),
+            })
+            .check_interval(Duration::from_millis(1))
+            .supervise("replay", || ReplayGenerator {
+                path: PathBuf::from("/nonexistent/event_gen/flight.csv"),
+                format: LogFormat::Csv,
+                speed: ReplaySpeed::AsFastAsPossible,
+                event_mapper: |_| Some(Event::Tick),
+            })
+            .start_with(&scheduler, s);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                health(&r),
+                Health::Failed { message } if message.starts_with("failed to read log")
+            ));
+        }
+        assert_eq!(health(&r), Health::GaveUp);
+        assert!(r.recv().is_err());
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn reports_stalled_and_finished_generators() {
+        let scheduler = Scheduler::new();
+        let (s, r) = mpsc::channel();
+        let mut handle = Supervisor::new(Event::Health)
+            .stall_timeout(Duration::from_millis(20))
+            .check_interval(Duration::from_millis(5))
+            .supervise("slow", || TickGenerator {
+                min_duration: Duration::from_millis(60),
+                event_producer: |_now, _prev| Event::Tick,
+            })
+            .supervise("once", || OneShotGenerator { value: Event::Tick })
+            .start_with(&scheduler, s);
+
+        let mut reports: Vec<(String, Health)> = Vec::new();
+        while reports.len() < 3 {
+            if let Event::Health(event) = r.recv().unwrap() {
+                reports.push((event.generator, event.health));
+            }
+        }
+        assert_eq!(reports[0], ("once".to_string(), Health::Finished));
+        assert!(matches!(&reports[1], (name, Health::Stalled { .. }) if name == "slow"));
+        assert_eq!(reports[2], ("slow".to_string(), Health::Resumed));
+
+        let stats = handle.generator_stats();
+        assert_eq!(stats[0].0, "slow");
+        assert!(stats[0].1.events_sent >= 1);
+        assert!(handle.stop().is_ok());
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn starts_generators_off_the_timer_thread() {
+        // Starting blocks until released, like opening a device which is slow to respond.
+        struct SlowStart(Arc<Mutex<mpsc::Receiver<()>>>);
+        impl EventGenerator<Event> for SlowStart {
+            type Handle = TimerHandle;
+            type Error = Infallible;
+            fn start(self, send_handle: Sender<Event>) -> Result<Self::Handle, Self::Error> {
+                let _ = self.0.lock().unwrap().recv();
+                OneShotGenerator { value: Event::Tick }.start(send_handle)
+            }
+        }
+
+        let scheduler = Scheduler::new();
+        let (release, released) = mpsc::channel();
+        let released = Arc::new(Mutex::new(released));
+        let (s, r) = mpsc::channel();
+        let mut handle = Supervisor::new(Event::Health)
+            .check_interval(Duration::from_millis(1))
+            .supervise("slow", move || SlowStart(released.clone()))
+            .start_with(&scheduler, s);
+
+        // Other tasks of the scheduler keep running while the generator starts.
+        let (ran, has_run) = mpsc::channel();
+        scheduler.schedule(Instant::now(), move |_now| {
+            ran.send(()).unwrap();
+            None
+        });
+        assert!(has_run.recv_timeout(Duration::from_secs(1)).is_ok());
+
+        release.send(()).unwrap();
+        assert_eq!(r.recv_timeout(Duration::from_secs(1)).unwrap(), Event::Tick);
+        assert_eq!(health(&r), Health::Finished);
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn generators_started_after_stop_are_stopped() {
+        // Signals that it is being started and blocks until released
+        struct SlowStart(mpsc::Sender<()>, Arc<Mutex<mpsc::Receiver<()>>>);
+        impl EventGenerator<Event> for SlowStart {
+            type Handle = TimerHandle;
+            type Error = Infallible;
+            fn start(self, send_handle: Sender<Event>) -> Result<Self::Handle, Self::Error> {
+                let _ = self.0.send(());
+                let _ = self.1.lock().unwrap().recv();
+                TickGenerator {
+                    min_duration: Duration::from_millis(1),
+                    event_producer: |_now, _prev| Event::Tick,
+                }
+                .start(send_handle)
+            }
+        }
+
+        let scheduler = Scheduler::new();
+        let (entered, starting) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let released = Arc::new(Mutex::new(released));
+        let (s, r) = mpsc::channel();
+        let mut handle = Supervisor::new(Event::Health)
+            .supervise("slow", move || SlowStart(entered.clone(), released.clone()))
+            .start_with(&scheduler, s);
+        starting.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        // Stopped from the timer thread, which does not wait for the supervisor thread
+        let (stopped, has_stopped) = mpsc::channel();
+        scheduler.schedule(Instant::now(), move |_now| {
+            stopped.send(handle.stop().is_ok()).unwrap();
+            None
+        });
+        assert!(has_stopped.recv_timeout(Duration::from_secs(1)).unwrap());
+        release.send(()).unwrap();
+
+        // All senders are dropped once the generator is stopped
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while r.recv_timeout(Duration::from_secs(1)).is_ok() {
+            assert!(Instant::now() < deadline, "generator still running");
+        }
+    }
+
+    #[test]
+    fn stop_stops_generators() {
+        let (s, r) = mpsc::channel();
+        let mut handle = Supervisor::new(Event::Health)
+            .supervise("ticks", || TickGenerator {
+                min_duration: Duration::from_millis(1),
+                event_producer: |_now, _prev| Event::Tick,
+            })
+            .start(s)
+            .unwrap();
+
+        assert_eq!(r.recv().unwrap(), Event::Tick);
+        assert!(handle.stop().is_ok());
+        while r.recv().is_ok() {}
+    }
+}